@@ -0,0 +1,62 @@
+use std::io::{ErrorKind, Read, Write};
+
+use fdc_core::Result;
+use fdc_core::model::*;
+
+// frames are [len (u32 LE) | data]
+const LEN_PREFIX: usize = 4;
+
+pub(crate) fn write_frame<W: Write>(to: &mut W, data: &[u8]) -> Result<()> {
+  to.write_all(&(data.len() as u32).to_le_bytes())?;
+  to.write_all(data)?;
+  Ok(())
+}
+
+pub(crate) fn read_frame<R: Read>(from: &mut R) -> Result<Option<Vec<u8>>> {
+  let mut len = [0u8; LEN_PREFIX];
+  match from.read_exact(&mut len) {
+    Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+    res => res?
+  }
+
+  let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+  from.read_exact(&mut data)?;
+  Ok(Some(data))
+}
+
+pub fn encode_record(record: &Record) -> Result<Vec<u8>> {
+  let mut to = Vec::with_capacity(framed_len(record)?);
+  write_frame(&mut to, &bincode::serialize(record)?)?;
+  Ok(to)
+}
+
+pub fn decode_record<R: Read>(from: &mut R) -> Result<Option<Record>> {
+  match read_frame(from)? {
+    None => Ok(None),
+    Some(frame) => Ok(Some(bincode::deserialize(&frame)?))
+  }
+}
+
+pub fn framed_len(record: &Record) -> Result<usize> {
+  Ok(LEN_PREFIX + bincode::serialized_size(record)? as usize)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tests::create_chain;
+
+  #[test]
+  fn record_framed_len() {
+    let chain = create_chain(2);
+    for record in chain.chain.iter() {
+      let encoded = encode_record(record).unwrap();
+      assert!(encoded.len() == framed_len(record).unwrap());
+
+      let decoded = decode_record(&mut encoded.as_slice()).unwrap().unwrap();
+      assert!(decoded.id() == record.id());
+    }
+
+    assert!(decode_record(&mut [0u8; 0].as_ref()).unwrap().is_none());
+  }
+}
@@ -1,10 +1,18 @@
-
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
 
 use serde::{Serialize, Deserialize};
-use fdc_core::crypto::{SecretKey, PublicKey};
+use sha2::{Digest, Sha256, Sha512};
+use fdc_core::{error, BoxError, Result};
+use fdc_core::crypto::{SecretKey, PublicKey, KeyPair, KeySize, LambdaKey, ExtSignature, G};
 use fdc_core::model::*;
 
+pub mod codec;
+mod storage;
+
+pub use storage::*;
+
 pub struct Config {
   pub values: HashMap<String, String>
 }
@@ -14,6 +22,196 @@ pub trait FdpNetwork {
   fn records(&self) -> RecordChain;
 }
 
+// resolves external blobs referenced by RDataRef::hfile (the SHA-512 of the blob)
+pub trait FileStore {
+  fn fetch(&self, hfile: &[u8]) -> Result<Vec<u8>>;
+}
+
+const VERIFY_CHANNEL_BOUND: usize = 64;
+const ANCHOR_DOMAIN: &[u8] = b"fdc-anchor";
+
+// verifies records in a background thread, forwarding each one with its result
+pub fn verify_channel(rx: Receiver<Record>, owner: &PublicKey) -> Receiver<Result<Record>> {
+  let owner = *owner;
+  let (tx, out) = sync_channel(VERIFY_CHANNEL_BOUND);
+
+  thread::spawn(move || {
+    for record in rx {
+      let res = if *record.owner() != owner {
+        Err(error("Unexpected record owner!"))
+      } else {
+        record.check().map(|_| record)
+      };
+
+      if tx.send(res).is_err() {
+        break
+      }
+    }
+  });
+
+  out
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// RecordSet (chains of one subject, keyed by table)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RecordSet {
+  pub chains: HashMap<String, RecordChain>
+}
+
+impl RecordSet {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, chain: RecordChain) -> Option<RecordChain> {
+    self.chains.insert(chain.table.clone(), chain)
+  }
+
+  pub fn tables(&self) -> Vec<&String> {
+    let mut tables = self.chains.keys().collect::<Vec<_>>();
+    tables.sort();
+    tables
+  }
+
+  pub fn owner_counts(&self) -> HashMap<[u8; 32], usize> {
+    let mut counts = HashMap::new();
+    for rn in self.chains.values().flat_map(|c| c.chain.iter()) {
+      *counts.entry(rn.owner().to_bytes()).or_insert(0) += 1;
+    }
+
+    counts
+  }
+
+  // signs the (table, lhash, len) of every chain, sorted by table name
+  pub fn seal(&self, keyp: &KeyPair) -> ExtSignature {
+    ExtSignature::sign(keyp, &self.digest())
+  }
+
+  pub fn verify_seal(&self, seal: &ExtSignature) -> Result<()> {
+    if !seal.verify(&self.digest()) {
+      Err("Invalid record set seal!")?
+    }
+
+    Ok(())
+  }
+
+  fn digest(&self) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    for table in self.tables() {
+      let chain = &self.chains[table];
+      hasher.input((table.len() as u64).to_le_bytes());
+      hasher.input(table);
+      hasher.input(&chain.lhash);
+      hasher.input((chain.chain.len() as u64).to_le_bytes());
+    }
+
+    hasher.result().to_vec()
+  }
+
+  // reports (table, index) pairs of records sharing the same ephemeral key
+  // a ratcheted record reuses the key of its predecessor by design, only that pair is exempt
+  pub fn detect_ephemeral_reuse(&self) -> Vec<(String, usize, String, usize)> {
+    let mut seen = HashMap::<[u8; 32], (&String, usize)>::new();
+    let mut reused = Vec::new();
+    for table in self.tables() {
+      let chain = &self.chains[table].chain;
+      for (i, rn) in chain.iter().enumerate() {
+        if rn.ratchet_step() != 0 && i > 0 && chain[i - 1].ephemeral_key() == rn.ephemeral_key() {
+          continue
+        }
+
+        match seen.get(&rn.ephemeral_key().to_bytes()) {
+          Some((ft, fi)) => reused.push(((*ft).clone(), *fi, table.clone(), i)),
+          None => { seen.insert(rn.ephemeral_key().to_bytes(), (table, i)); }
+        }
+      }
+    }
+
+    reused
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// ChainHeader
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ChainHeader {
+  pub id: String,
+  pub table: String,
+
+  pub lhash: Vec<u8>, // last Record hash
+  pub len: usize,
+  pub owner: PublicKey
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Checkpoint
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Checkpoint {
+  pub index: usize,
+  pub lhash: Vec<u8>, // Record hash at index
+  pub sig: ExtSignature
+}
+
+impl Checkpoint {
+  fn hash(index: usize, lhash: &[u8]) -> Vec<u8> {
+    let dhash = Sha512::new()
+      .chain((index as u64).to_le_bytes())
+      .chain(lhash)
+      .result();
+
+    dhash.to_vec()
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// VerifyCache
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Default)]
+pub struct VerifyCache {
+  verified: HashSet<Vec<u8>>,
+  checks: usize
+}
+
+impl VerifyCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn contains(&self, record: &Record) -> bool {
+    self.verified.contains(&VerifyCache::key(record))
+  }
+
+  // number of signature checks performed through this cache
+  pub fn checks(&self) -> usize {
+    self.checks
+  }
+
+  fn check(&mut self, record: &Record) -> Result<Vec<u8>> {
+    let key = VerifyCache::key(record);
+    if !self.verified.contains(&key) {
+      self.checks += 1;
+      record.check()?;
+      self.verified.insert(key);
+    }
+
+    Ok(record.id())
+  }
+
+  // the id doesn't cover the signature, a cached id alone would accept any owner or signature for it
+  fn key(record: &Record) -> Vec<u8> {
+    let mut key = record.id();
+    let sig = record.signature();
+    key.extend_from_slice(&sig.sig.M.to_bytes());
+    key.extend_from_slice(sig.sig.p.as_bytes());
+    key.extend_from_slice(&sig.key.to_bytes());
+    key
+  }
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // RecordChain
 //-----------------------------------------------------------------------------------------------------------
@@ -21,52 +219,1267 @@ pub trait FdpNetwork {
 pub struct RecordChain {
   pub id: String,
   pub table: String,
-  
+
   pub lhash: Vec<u8>, // last Record hash
   pub chain: Vec<Record>
 }
 
 impl RecordChain {
-  pub fn kn(&self) -> &PublicKey {
-    &self.chain.last().unwrap().data.kn
+  pub fn create(id: &str, table: &str, keyp: &KeyPair, ekey: &PublicKey, ksize: KeySize, hfile: &[u8]) -> Result<(RecordChain, LambdaKey)> {
+    let salt = salt(id, table);
+    let (lambda, head) = Record::head(keyp, ekey, &salt, RData::head(ksize, hfile));
+
+    let chain = RecordChain::new(id.into(), table.into(), head)?;
+    Ok((chain, lambda))
+  }
+
+  pub fn kn(&self) -> Option<&PublicKey> {
+    self.chain.last().map(|rn| rn.ephemeral_key())
   }
 
-  pub fn new(head: Record) -> Result<Self> {
+  pub fn new(id: String, table: String, head: Record) -> Result<Self> {
     let lhash = head.check()?;
-    if head.id.is_none() {
+    if head.hprev != salt(&id, &table) {
       Err("Record is not a head type!")?
     }
-    
-    Ok(Self { lhash, chain: vec![head] })
+
+    Ok(Self { id, table, lhash, chain: vec![head] })
+  }
+
+  // the head signature covers hprev, which must be the salt of this chain (id, table)
+  pub fn verify_identity(&self) -> Result<()> {
+    let head = self.chain.first().ok_or_else(|| error("Empty record chain!"))?;
+    if head.hprev != salt(&self.id, &self.table) {
+      Err("Head record doesn't belong to this chain!")?
+    }
+
+    head.check()?;
+    Ok(())
+  }
+
+  // a chain slot without records, lhash is the salt so the first push must be the head
+  pub fn empty(id: &str, table: &str) -> Self {
+    Self { id: id.into(), table: table.into(), lhash: salt(id, table), chain: Vec::new() }
+  }
+
+  pub fn is_initialized(&self) -> bool {
+    !self.chain.is_empty()
+  }
+
+  // None for an empty chain, there is no head owner to advertise
+  pub fn header(&self) -> Option<ChainHeader> {
+    let head = self.chain.first()?;
+    Some(ChainHeader {
+      id: self.id.clone(),
+      table: self.table.clone(),
+      lhash: self.lhash.clone(),
+      len: self.chain.len(),
+      owner: *head.owner()
+    })
+  }
+
+  pub fn push(&mut self, tail: Record) -> Result<()> {
+    let dhash = tail.check()?;
+    self.append(tail, dhash)
+  }
+
+  // hash links are validated but signatures are not, only for records already verified upstream
+  pub fn push_unchecked(&mut self, tail: Record) -> Result<()> {
+    let dhash = tail.id();
+    self.append(tail, dhash)
+  }
+
+  fn append(&mut self, tail: Record, dhash: Vec<u8>) -> Result<()> {
+    let salt = salt(&self.id, &self.table);
+    if !self.is_initialized() {
+      if tail.hprev != salt {
+        Err("Empty chain requires a head record first!")?
+      }
+    } else if tail.hprev == salt {
+      Err("Attempted to push a head record as tail!")?
+    }
+
+    if self.lhash != tail.hprev {
+      Err("Incorrect hash chain!")?
+    }
+
+    self.lhash = dhash;
+    self.chain.push(tail);
+
+    Ok(())
+  }
+
+  pub fn push_if_owner(&mut self, tail: Record, allowed: &HashSet<[u8; 32]>) -> Result<()> {
+    if !allowed.contains(&tail.owner().to_bytes()) {
+      Err("Record owner is not allowed!")?
+    }
+
+    self.push(tail)
+  }
+
+  pub fn verify_at(&self, index: usize, record: &Record) -> Result<()> {
+    let current = self.chain.get(index).ok_or_else(|| error("Record index out of bounds!"))?;
+    if current.id() != record.id() {
+      Err("Record not found at index!")?
+    }
+
+    let hprev = match index {
+      0 => salt(&self.id, &self.table),
+      _ => self.chain[index - 1].id()
+    };
+
+    if record.hprev != hprev {
+      Err("Incorrect hash chain!")?
+    }
+
+    Ok(())
   }
 
-  pub fn push(&mut self, tail: Rn) -> Result<()> {
-      let dhash = tail.check()?;
+  pub fn prefix(&self, up_to: usize) -> Result<RecordChain> {
+    if up_to >= self.chain.len() {
+      Err("Record index out of bounds!")?
+    }
+
+    let chain = self.chain[..=up_to].to_vec();
+    let lhash = chain[up_to].id();
+
+    Ok(Self { id: self.id.clone(), table: self.table.clone(), lhash, chain })
+  }
+
+  // records after the one with id == after, the salt selects the whole chain
+  pub fn tail_since(&self, after: &[u8]) -> Result<Vec<Record>> {
+    Ok(self.chain[self.position_after(after)?..].to_vec())
+  }
+
+  // a headless chain with only the records after `after`, lhash is `after` when nothing is new
+  pub fn tail_since_cloned(&self, after: &[u8]) -> Result<RecordChain> {
+    let chain = self.tail_since(after)?;
+    let lhash = chain.last().map(|rn| rn.id()).unwrap_or_else(|| after.to_vec());
+
+    Ok(Self { id: self.id.clone(), table: self.table.clone(), lhash, chain })
+  }
+
+  fn position_after(&self, after: &[u8]) -> Result<usize> {
+    if after == salt(&self.id, &self.table).as_slice() {
+      return Ok(0)
+    }
+
+    let index = self.chain.iter().position(|rn| rn.id() == after)
+      .ok_or_else(|| error("Record hash not found in the chain!"))?;
+
+    Ok(index + 1)
+  }
+
+  pub fn verify(&self) -> Result<()> {
+    let salt = salt(&self.id, &self.table);
+    if self.chain.first().ok_or_else(|| error("Empty record chain!"))?.hprev != salt {
+      Err("Record 0 is not a head type!")?
+    }
+
+    let mut hprev = salt;
+    for (i, rn) in self.chain.iter().enumerate() {
+      if rn.hprev != hprev {
+        Err(format!("Broken hash link at record {}!", i))?
+      }
+
+      hprev = rn.check().map_err(|e| format!("Invalid record {}: {}", i, e))?;
+    }
+
+    if hprev != self.lhash {
+      Err("Chain tip doesn't match lhash!")?
+    }
+
+    Ok(())
+  }
+
+  // reports every broken link and bad signature, links are checked against the previous record as stored
+  pub fn verify_all(&self) -> Vec<(usize, BoxError)> {
+    let mut errors = Vec::new();
+    let mut hprev = salt(&self.id, &self.table);
+    for (i, rn) in self.chain.iter().enumerate() {
+      if rn.hprev != hprev {
+        errors.push((i, error("Incorrect hash chain!")));
+      }
+
+      if let Err(e) = rn.check() {
+        errors.push((i, e));
+      }
+
+      hprev = rn.id();
+    }
+
+    if hprev != self.lhash {
+      errors.push((self.chain.len(), error("Chain tip doesn't match lhash!")));
+    }
+
+    errors
+  }
+
+  // signatures of records already in the cache are skipped, hash links are always checked
+  pub fn verify_cached(&self, cache: &mut VerifyCache) -> Result<()> {
+    let mut hprev = salt(&self.id, &self.table);
+    for rn in self.chain.iter() {
+      if rn.hprev != hprev {
+        Err("Incorrect hash chain!")?
+      }
+
+      hprev = cache.check(rn)?;
+    }
+
+    if hprev != self.lhash {
+      Err("Incorrect hash chain!")?
+    }
+
+    Ok(())
+  }
+
+  // signed checkpoints at every N-th record (indexes N-1, 2N-1, ...)
+  pub fn checkpoints(&self, every: usize, keyp: &KeyPair) -> Vec<Checkpoint> {
+    if every == 0 {
+      return Vec::new()
+    }
+
+    self.chain.iter().enumerate()
+      .filter(|(i, _)| (i + 1) % every == 0)
+      .map(|(index, rn)| {
+        let lhash = rn.id();
+        let sig = ExtSignature::sign(keyp, &Checkpoint::hash(index, &lhash));
+        Checkpoint { index, lhash, sig }
+      })
+      .collect()
+  }
+
+  pub fn verify_checkpoint(&self, cp: &Checkpoint) -> Result<()> {
+    let rn = self.chain.get(cp.index).ok_or_else(|| error("Checkpoint index out of bounds!"))?;
+    if rn.id() != cp.lhash {
+      Err("Checkpoint hash mismatch!")?
+    }
+
+    if cp.sig.key != *self.chain[0].owner() || !cp.sig.verify(&Checkpoint::hash(cp.index, &cp.lhash)) {
+      Err("Invalid checkpoint signature!")?
+    }
+
+    Ok(())
+  }
+
+  // compact proof of authorship, one owner signature over commitment() covers every record
+  pub fn attest(&self, keyp: &KeyPair) -> Result<ExtSignature> {
+    let owner = self.chain.first().ok_or_else(|| error("Empty record chain!"))?.owner();
+    if keyp.key != *owner {
+      Err("Only the chain owner can attest!")?
+    }
+
+    for rn in self.chain.iter() {
+      if rn.owner() != owner {
+        Err("Records have different owners!")?
+      }
+
+      rn.check()?;
+    }
+
+    Ok(ExtSignature::sign(keyp, &self.commitment()?))
+  }
+
+  // a single signature check, the record signatures were verified by the owner in attest()
+  pub fn verify_attestation(&self, att: &ExtSignature) -> Result<()> {
+    if self.chain.is_empty() {
+      Err("Empty record chain!")?
+    }
+
+    if self.chain.iter().any(|rn| *rn.owner() != att.key) {
+      Err("Attestation key is not the owner of every record!")?
+    }
+
+    if !att.verify(&self.commitment()?) {
+      Err("Invalid chain attestation!")?
+    }
+
+    Ok(())
+  }
+
+  // rolling commitment H(...H(H(id || table) || rec_0)... || rec_n) for external anchoring
+  pub fn commitment(&self) -> Result<[u8; 64]> {
+    let mut acc = Sha512::new()
+      .chain(&self.id)
+      .chain(&self.table)
+      .result();
+
+    for rn in self.chain.iter() {
+      acc = Sha512::new()
+        .chain(acc)
+        .chain(bincode::serialize(rn)?)
+        .result();
+    }
+
+    let mut commitment = [0u8; 64];
+    commitment.copy_from_slice(acc.as_slice());
+    Ok(commitment)
+  }
+
+  // 32-byte digest of commitment(), fits an OP_RETURN or any other anchoring payload
+  pub fn anchor_payload(&self) -> Result<[u8; 32]> {
+    let dhash = Sha256::new()
+      .chain(ANCHOR_DOMAIN)
+      .chain(&self.commitment()?[..])
+      .result();
+
+    let mut payload = [0u8; 32];
+    payload.copy_from_slice(dhash.as_slice());
+    Ok(payload)
+  }
+
+  pub fn verify_anchor_payload(&self, payload: &[u8; 32]) -> bool {
+    self.anchor_payload().ok() == Some(*payload)
+  }
+
+  // verifies a suffix against a trusted head, bridging the skipped records with their links (id preimages).
+  // Every link must continue the hash chain from the head, only the head and suffix signatures are verified.
+  pub fn verify_suffix(head: &Record, suffix: &[Record], skipped: &[RecordLink]) -> Result<()> {
+    if suffix.is_empty() {
+      Err("Empty record suffix!")?
+    }
+
+    let mut hprev = head.check()?;
+    for link in skipped.iter() {
+      if link.hprev != hprev {
+        Err("Incorrect bridge link!")?
+      }
+
+      hprev = link.id();
+    }
+
+    for rn in suffix.iter() {
+      if rn.hprev != hprev {
+        Err("Incorrect hash chain!")?
+      }
+
+      hprev = rn.check()?;
+    }
+
+    Ok(())
+  }
+
+  pub fn first_broken_link(&self) -> Option<usize> {
+    let mut hprev = salt(&self.id, &self.table);
+    for (i, rn) in self.chain.iter().enumerate() {
+      if rn.hprev != hprev {
+        return Some(i)
+      }
+
+      hprev = rn.id();
+    }
+
+    None
+  }
+
+  pub fn contains_compromised_ephemeral(&self, bad: &HashSet<[u8; 32]>) -> Vec<usize> {
+    self.chain.iter().enumerate()
+      .filter(|(_, rn)| bad.contains(&rn.ephemeral_key().to_bytes()))
+      .map(|(i, _)| i)
+      .collect()
+  }
+
+  // pairs (i, j) of records signed with the same commitment M over different digests, a reused nonce leaks the signer key
+  pub fn detect_nonce_reuse(&self) -> Vec<(usize, usize)> {
+    let mut seen = HashMap::<[u8; 32], Vec<(usize, Vec<u8>)>>::new();
+    let mut reused = Vec::new();
+    for (j, rn) in self.chain.iter().enumerate() {
+      let dhash = rn.id();
+      let sig = rn.signature();
+      let commit = sig.sig.recover_commitment(&sig.key, &dhash).to_bytes();
+
+      let previous = seen.entry(commit).or_default();
+      for (i, ihash) in previous.iter() {
+        if *ihash != dhash {
+          reused.push((*i, j));
+        }
+      }
+
+      previous.push((j, dhash));
+    }
+
+    reused
+  }
+
+  // re-encrypts every record under the salt of new_id, records are re-signed with keyp
+  pub fn migrate_salt(&self, master: &SecretKey, keyp: &KeyPair, new_id: &str) -> Result<RecordChain> {
+    let old_salt = salt(&self.id, &self.table);
+    let new_salt = salt(new_id, &self.table);
+    let ekey = master * G;
+
+    let mut migrated: Option<RecordChain> = None;
+    let mut lambda: Option<LambdaKey> = None;
+    for rn in self.chain.iter() {
+      let mut rd = rn.data(&rn.lambda(master, &old_salt), &old_salt)?;
+      rd.lprev = lambda;
+
+      lambda = Some(match migrated.as_mut() {
+        None => {
+          let (ln, head) = Record::head(keyp, &ekey, &new_salt, rd);
+          migrated = Some(RecordChain::new(new_id.into(), self.table.clone(), head)?);
+          ln
+        },
+        Some(chain) => {
+          let (ln, tail) = Record::tail(keyp, &ekey, &chain.lhash, &new_salt, rd);
+          chain.push(tail)?;
+          ln
+        }
+      });
+    }
+
+    migrated.ok_or_else(|| error("Empty record chain!"))
+  }
+
+  pub fn serialized_size(&self) -> Result<u64> {
+    // bincode computes the exact size without allocating the output
+    Ok(bincode::serialized_size(self)?)
+  }
+
+  pub fn encrypted_refs(&self) -> Vec<&[u8]> {
+    self.chain.iter().map(|rn| rn.ciphertext()).collect()
+  }
+
+  pub fn recover(&self, alpha: &PublicKey) -> Result<Vec<RDataRef>> {
+    let chain = self.decrypt(alpha)?;
+    Ok(chain.into_iter().map(|rd| rd.dref).collect())
+  }
 
-      let hprev = tail.hprev.as_ref().ok_or_else(|| error("Record is not a tail type!"))?;
-      if &self.lhash != hprev {
-          Err("Incorrect hash chain!")?
+  pub fn verify_with_blobs(&self, store: &dyn FileStore, alpha: &PublicKey) -> Result<()> {
+    for (i, dref) in self.recover(alpha)?.iter().enumerate() {
+      // hfile is the blob SHA-512 or the root of its FileManifest (RData::head_file)
+      let blob = store.fetch(dref.hfile())?;
+      if Sha512::digest(&blob).as_slice() != dref.hfile()
+        && FileManifest::from_reader(FILE_CHUNK_SIZE, blob.as_slice())?.root != dref.hfile() {
+        Err(format!("Blob hash mismatch at index {}!", i))?
       }
+    }
+
+    Ok(())
+  }
+
+  pub fn lambda_graph(&self, alpha: &PublicKey) -> Result<Vec<Option<LambdaKey>>> {
+    let chain = self.decrypt(alpha)?;
+    Ok(chain.into_iter().map(|rd| rd.lprev).collect())
+  }
+
+  // derives every record lambda from the master key, ratcheted records don't link back through lprev
+  pub fn recover_master(&self, master: &SecretKey) -> Result<Vec<RDataRef>> {
+    let salt = salt(&self.id, &self.table);
+    self.chain.iter()
+      .map(|rn| Ok(rn.data(&rn.lambda(master, &salt), &salt)?.dref))
+      .collect()
+  }
+
+  // decrypts from the last record back to the head following the lprev links
+  pub fn recover_from(&self, lambda: LambdaKey) -> Result<Vec<RDataRef>> {
+    let chain = self.decrypt_from(lambda)?;
+    Ok(chain.into_iter().map(|rd| rd.dref).collect())
+  }
+
+  fn decrypt(&self, alpha: &PublicKey) -> Result<Vec<RData>> {
+    let salt = salt(&self.id, &self.table);
+    self.decrypt_from(LambdaKey::new(alpha, &salt))
+  }
+
+  fn decrypt_from(&self, lambda: LambdaKey) -> Result<Vec<RData>> {
+    let salt = salt(&self.id, &self.table);
+    let mut lambda = Some(lambda);
+    let mut chain = Vec::<RData>::new();
+    for rn in self.chain.iter().rev() {
+      let current = lambda.ok_or_else(|| error("Incorrect lambda chain!"))?;
+      let data = rn.data(&current, &salt)?;
+      lambda = data.lprev.clone();
+      chain.push(data);
+    }
+
+    chain.reverse();
+    Ok(chain)
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// ChainWriter (builds a chain file by file, threading hprev and lprev)
+//-----------------------------------------------------------------------------------------------------------
+pub struct ChainWriter {
+  keyp: KeyPair,
+  ekey: PublicKey,
+  salt: Vec<u8>,
+
+  lambda: Option<LambdaKey>, // lambda of the last record
+  chain: RecordChain
+}
+
+impl ChainWriter {
+  pub fn new(id: &str, table: &str, keyp: &KeyPair, ekey: &PublicKey) -> Self {
+    Self { keyp: keyp.clone(), ekey: *ekey, salt: salt(id, table), lambda: None, chain: RecordChain::empty(id, table) }
+  }
 
-      self.lhash = dhash;
-      self.chain.push(tail);
+  pub fn push_file(&mut self, ksize: KeySize, hfile: &[u8]) -> Result<()> {
+    let (lambda, record) = match &self.lambda {
+      None => Record::head(&self.keyp, &self.ekey, &self.salt, RData::head(ksize, hfile)),
+      Some(lprev) => Record::tail(&self.keyp, &self.ekey, &self.chain.lhash, &self.salt, RData::tail(ksize, lprev.clone(), hfile))
+    };
 
-      Ok(())
+    self.chain.push(record)?;
+    self.lambda = Some(lambda);
+    Ok(())
   }
 
-  pub fn recover(&self, alpha: &CompressedRistretto) -> Result<Vec<RnFileRef>> {
-      let id = self.id();
-      let set = self.set();
+  pub fn lambda(&self) -> Option<&LambdaKey> {
+    self.lambda.as_ref()
+  }
+
+  pub fn finish(self) -> RecordChain {
+    self.chain
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use fdc_core::crypto::Signature;
+
+  const ID: &str = "subject-id";
+  const TABLE: &str = "table-id";
+
+  pub(crate) fn create_chain(size: usize) -> RecordChain {
+    let ekp = KeyPair::rand(); // master key-pair
+    let skp = KeyPair::rand(); // source key-pair
+    create_chain_with(&ekp, &skp, size)
+  }
+
+  fn create_chain_with(ekp: &KeyPair, skp: &KeyPair, size: usize) -> RecordChain {
+    let salt = salt(ID, TABLE);
+
+    let rd = RData::head(KeySize::S128, b"file-0");
+    let (mut lambda, head) = Record::head(skp, &ekp.key, &salt, rd);
+    let mut chain = RecordChain::new(ID.into(), TABLE.into(), head).unwrap();
+
+    for i in 1..size {
+      let rd = RData::tail(KeySize::S128, lambda, format!("file-{}", i).as_bytes());
+      let (ln, tail) = Record::tail(skp, &ekp.key, &chain.lhash, &salt, rd);
+      chain.push(tail).unwrap();
+      lambda = ln;
+    }
+
+    chain
+  }
+
+  // the same record fields under another signature, as a peer could send it on the wire
+  fn with_signature(rn: &Record, sig: ExtSignature) -> Record {
+    #[derive(Serialize, Deserialize)]
+    struct Encoded {
+      hprev: Vec<u8>,
+      algo: HashAlgo,
+      data: REncData,
+      sig: ExtSignature
+    }
+
+    let mut encoded: Encoded = bincode::deserialize(&bincode::serialize(rn).unwrap()).unwrap();
+    encoded.sig = sig;
+    bincode::deserialize(&bincode::serialize(&encoded).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn verify_records_channel() {
+    let chain = create_chain(3);
+    let owner = *chain.chain[0].owner();
+
+    let mut invalid = chain.chain[1].clone();
+    invalid.hprev = vec![0u8; 64];
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let out = verify_channel(rx, &owner);
+
+    tx.send(chain.chain[0].clone()).unwrap();
+    tx.send(invalid).unwrap();
+    tx.send(chain.chain[2].clone()).unwrap();
+    drop(tx);
+
+    let results = out.iter().collect::<Vec<_>>();
+    assert!(results.len() == 3);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(results[2].as_ref().unwrap().id() == chain.chain[2].id());
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let out = verify_channel(rx, &KeyPair::rand().key);
+    tx.send(chain.chain[0].clone()).unwrap();
+    assert!(out.recv().unwrap().is_err());
+  }
+
+  #[test]
+  fn record_set_ephemeral_reuse() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let mut set = RecordSet::new();
+    set.insert(create_chain_with(&ekp, &skp, 3));
+    assert!(set.detect_ephemeral_reuse().is_empty());
+
+    let mut other = create_chain_with(&ekp, &skp, 2);
+    other.table = "other-table".into();
+    other.chain.push(set.chains[TABLE].chain[1].clone());
+    set.insert(other);
+
+    let reused = set.detect_ephemeral_reuse();
+    assert!(reused == vec![("other-table".into(), 2, TABLE.into(), 1)]);
+  }
+
+  #[test]
+  fn record_set_ratchet_reuse() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+    let salt = salt(ID, TABLE);
+
+    let mut chain = create_chain_with(&ekp, &skp, 2);
+    let lambda = chain.chain[1].lambda(&ekp.secret, &salt);
+    let (_, tail) = Record::tail_ratchet(&skp, &chain.chain[1], &lambda, &salt, RData::head(KeySize::S128, b"file-2")).unwrap();
+    chain.push(tail).unwrap();
+
+    // the ratchet step shares the key with the record it ratchets from
+    let mut set = RecordSet::new();
+    set.insert(chain.clone());
+    assert!(set.detect_ephemeral_reuse().is_empty());
+
+    // but not with records of other chains
+    let mut other = create_chain_with(&ekp, &skp, 2);
+    other.table = "other-table".into();
+    other.chain.push(chain.chain[2].clone());
+    set.insert(other);
+
+    let reused = set.detect_ephemeral_reuse();
+    assert!(reused == vec![("other-table".into(), 2, TABLE.into(), 1)]);
+  }
 
-      let mut lambda = Some(LambdaKey::new(alpha, id, set));
-      let mut chain = Vec::<RnFileRef>::new();
-      for rn in self.chain.iter().rev() {
-          let data = rn.data.data(&lambda.as_ref().unwrap())?;
-          lambda = data.lambda_prev;
-          chain.push(data.file);
+  #[test]
+  fn create_chain_s256() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let (chain, lambda) = RecordChain::create(ID, TABLE, &skp, &ekp.key, KeySize::S256, b"file-0").unwrap();
+    assert!(chain.chain.len() == 1);
+    assert!(lambda == chain.chain[0].lambda(&ekp.secret, &salt(ID, TABLE)));
+
+    let refs = chain.recover(&(&ekp.secret * chain.kn().unwrap())).unwrap();
+    assert!(refs.len() == 1);
+    assert!(refs[0].hfile() == b"file-0");
+    assert!(*refs[0].ksize() == KeySize::S256);
+    assert!(refs[0].dn().len() == KeySize::S256.size());
+  }
+
+  #[test]
+  fn record_set_seal() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let first = create_chain_with(&ekp, &skp, 3);
+    let mut second = create_chain_with(&ekp, &skp, 2);
+    second.table = "other-table".into();
+
+    let mut set = RecordSet::new();
+    set.insert(first.clone());
+    set.insert(second.clone());
+    let seal = set.seal(&skp);
+    assert!(set.verify_seal(&seal).is_ok());
+
+    let mut reordered = RecordSet::new();
+    reordered.insert(second);
+    reordered.insert(first);
+    assert!(reordered.verify_seal(&seal).is_ok());
+
+    reordered.chains.get_mut(TABLE).unwrap().lhash = vec![0u8; 64];
+    assert!(reordered.verify_seal(&seal).is_err());
+  }
+
+  #[test]
+  fn record_set_owner_counts() {
+    let ekp = KeyPair::rand();
+    let skp1 = KeyPair::rand();
+    let skp2 = KeyPair::rand();
+
+    let mut set = RecordSet::new();
+    set.insert(create_chain_with(&ekp, &skp1, 3));
+
+    let salt = salt(ID, "other-table");
+    let (mut lambda, head) = Record::head(&skp1, &ekp.key, &salt, RData::head(KeySize::S128, b"file-0"));
+    let mut other = RecordChain::new(ID.into(), "other-table".into(), head).unwrap();
+    for i in 1..3 {
+      let rd = RData::tail(KeySize::S128, lambda, format!("file-{}", i).as_bytes());
+      let (ln, tail) = Record::tail(&skp2, &ekp.key, &other.lhash, &salt, rd);
+      other.push(tail).unwrap();
+      lambda = ln;
+    }
+    set.insert(other);
+
+    let counts = set.owner_counts();
+    assert!(counts.len() == 2);
+    assert!(counts[&skp1.key.to_bytes()] == 4);
+    assert!(counts[&skp2.key.to_bytes()] == 2);
+  }
+
+  #[test]
+  fn chain_header() {
+    let chain = create_chain(3);
+    let header = chain.header().unwrap();
+
+    assert!(header.lhash == chain.chain.last().unwrap().id());
+    assert!(header.owner == *chain.chain[0].owner());
+    assert!(header.len == 3);
+
+    let data = bincode::serialize(&header).unwrap();
+    let loaded: ChainHeader = bincode::deserialize(&data).unwrap();
+    assert!(header == loaded);
+
+    assert!(RecordChain::empty(ID, TABLE).header().is_none());
+  }
+
+  struct MemoryStore(HashMap<Vec<u8>, Vec<u8>>);
+
+  impl FileStore for MemoryStore {
+    fn fetch(&self, hfile: &[u8]) -> Result<Vec<u8>> {
+      self.0.get(hfile).cloned().ok_or_else(|| error("Blob not found!"))
+    }
+  }
+
+  #[test]
+  fn chain_empty() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+    let chain = create_chain_with(&ekp, &skp, 2);
+
+    let mut empty = RecordChain::empty(ID, TABLE);
+    assert!(!empty.is_initialized());
+    assert!(empty.kn().is_none());
+    assert!(empty.push(chain.chain[1].clone()).is_err());
+
+    empty.push(chain.chain[0].clone()).unwrap();
+    assert!(empty.is_initialized());
+    assert!(empty.push(chain.chain[0].clone()).is_err());
+
+    empty.push(chain.chain[1].clone()).unwrap();
+    assert!(empty.lhash == chain.lhash);
+    assert!(empty.verify().is_ok());
+  }
+
+  #[test]
+  fn chain_new() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let (_, head) = Record::head(&skp, &ekp.key, &salt(ID, TABLE), RData::head(KeySize::S128, b"file-0"));
+    let chain = RecordChain::new(ID.into(), TABLE.into(), head.clone()).unwrap();
+    assert!(chain.id == ID && chain.table == TABLE);
+    assert!(chain.chain[0].hprev == salt(&chain.id, &chain.table));
+    assert!(chain.lhash == head.id());
+
+    assert!(RecordChain::new("other-id".into(), TABLE.into(), head.clone()).is_err());
+    assert!(RecordChain::new(ID.into(), "other-table".into(), head).is_err());
+  }
+
+  #[test]
+  fn chain_recover() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let chain = create_chain_with(&ekp, &skp, 2);
+    let refs = chain.recover(&(&ekp.secret * chain.kn().unwrap())).unwrap();
+    assert!(refs.len() == 2);
+    assert!(refs[0].hfile() == b"file-0");
+    assert!(refs[1].hfile() == b"file-1");
+  }
+
+  #[test]
+  fn chain_writer() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let mut writer = ChainWriter::new(ID, TABLE, &skp, &ekp.key);
+    assert!(writer.lambda().is_none());
+    for i in 0..3 {
+      writer.push_file(KeySize::S128, format!("file-{}", i).as_bytes()).unwrap();
+    }
+
+    let last = writer.lambda().unwrap().clone();
+    let chain = writer.finish();
+    assert!(chain.chain.len() == 3);
+    assert!(chain.verify().is_ok());
+
+    let refs = chain.recover(&(&ekp.secret * chain.kn().unwrap())).unwrap();
+    assert!(refs.iter().map(|r| r.hfile().to_vec()).collect::<Vec<_>>() == vec![b"file-0".to_vec(), b"file-1".to_vec(), b"file-2".to_vec()]);
+    assert!(chain.recover_from(last).unwrap().len() == 3);
+  }
+
+  #[test]
+  fn chain_verify_with_blobs() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let blobs = [b"blob-0".to_vec(), b"blob-1".to_vec(), fdc_core::rand(2 * FILE_CHUNK_SIZE + 5)];
+    let mut hfiles = blobs[..2].iter().map(|b| Sha512::digest(b).to_vec()).collect::<Vec<_>>();
+    hfiles.push(FileManifest::from_reader(FILE_CHUNK_SIZE, blobs[2].as_slice()).unwrap().root);
+
+    let (mut chain, mut lambda) = RecordChain::create(ID, TABLE, &skp, &ekp.key, KeySize::S128, &hfiles[0]).unwrap();
+    for hfile in hfiles[1..].iter() {
+      let rd = RData::tail(KeySize::S128, lambda, hfile);
+      let (ln, tail) = Record::tail(&skp, &ekp.key, &chain.lhash, &salt(ID, TABLE), rd);
+      chain.push(tail).unwrap();
+      lambda = ln;
+    }
+
+    let alpha = &ekp.secret * chain.kn().unwrap();
+    let mut store = MemoryStore(hfiles.iter().cloned().zip(blobs.iter().cloned()).collect());
+    assert!(chain.verify_with_blobs(&store, &alpha).is_ok());
+
+    let mut tampered = blobs[2].clone();
+    tampered[FILE_CHUNK_SIZE] ^= 1;
+    store.0.insert(hfiles[2].clone(), tampered);
+    let err = chain.verify_with_blobs(&store, &alpha).err().unwrap();
+    assert!(err.to_string() == "Blob hash mismatch at index 2!");
+
+    store.0.insert(hfiles[1].clone(), b"tampered".to_vec());
+    let err = chain.verify_with_blobs(&store, &alpha).err().unwrap();
+    assert!(err.to_string() == "Blob hash mismatch at index 1!");
+  }
+
+  #[test]
+  fn chain_lambda_graph() {
+    let ekp = KeyPair::rand();
+    let chain = create_chain_with(&ekp, &KeyPair::rand(), 4);
+
+    let alpha = &ekp.secret * chain.kn().unwrap();
+    let graph = chain.lambda_graph(&alpha).unwrap();
+
+    assert!(graph.len() == 4);
+    assert!(graph[0].is_none());
+    for (rn, lprev) in chain.chain.iter().zip(graph[1..].iter()) {
+      assert!(*lprev == Some(rn.lambda(&ekp.secret, &salt(ID, TABLE))));
+    }
+  }
+
+  #[test]
+  fn chain_commitment() {
+    let chain = create_chain(3);
+    assert!(chain.commitment().unwrap()[..] == chain.clone().commitment().unwrap()[..]);
+
+    for i in 0..3 {
+      let mut altered = chain.clone();
+      altered.chain[i].hprev[0] ^= 1;
+      assert!(altered.commitment().unwrap()[..] != chain.commitment().unwrap()[..]);
+    }
+
+    let mut renamed = chain.clone();
+    renamed.table = "other-table".into();
+    assert!(renamed.commitment().unwrap()[..] != chain.commitment().unwrap()[..]);
+  }
+
+  #[test]
+  fn chain_anchor_payload() {
+    let mut chain = create_chain(3);
+    let payload = chain.anchor_payload().unwrap();
+
+    let expected = Sha256::new().chain(ANCHOR_DOMAIN).chain(&chain.commitment().unwrap()[..]).result();
+    assert!(payload[..] == expected[..]);
+    assert!(chain.verify_anchor_payload(&payload));
+
+    chain.chain[2].hprev[0] ^= 1;
+    assert!(!chain.verify_anchor_payload(&payload));
+  }
+
+  #[test]
+  fn chain_prefix() {
+    let chain = create_chain(5);
+    let prefix = chain.prefix(2).unwrap();
+
+    assert!(prefix.chain.len() == 3);
+    assert!(prefix.lhash == chain.chain[2].id());
+    assert!(prefix.first_broken_link().is_none());
+    assert!(prefix.chain.iter().all(|rn| rn.check().is_ok()));
+
+    assert!(chain.prefix(5).is_err());
+  }
+
+  #[test]
+  fn chain_tail_since() {
+    let chain = create_chain(4);
+
+    let tail = chain.tail_since(&chain.chain[0].id()).unwrap();
+    assert!(tail.len() == 3);
+    assert!(tail.iter().zip(chain.chain[1..].iter()).all(|(a, b)| a.id() == b.id()));
+    assert!(RecordChain::verify_suffix(&chain.chain[0], &tail, &[]).is_ok());
+
+    assert!(chain.tail_since(&chain.lhash).unwrap().is_empty());
+    assert!(chain.tail_since(&salt(ID, TABLE)).unwrap().len() == 4);
+    assert!(chain.tail_since(b"unknown-hash").is_err());
+
+    let cloned = chain.tail_since_cloned(&chain.chain[1].id()).unwrap();
+    assert!(cloned.chain.len() == 2 && cloned.lhash == chain.lhash);
+
+    let cloned = chain.tail_since_cloned(&chain.lhash).unwrap();
+    assert!(cloned.chain.is_empty() && cloned.lhash == chain.lhash);
+  }
+
+  #[test]
+  fn chain_checkpoints() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let chain = create_chain_with(&ekp, &skp, 7);
+    let cps = chain.checkpoints(3, &skp);
+
+    assert!(cps.iter().map(|cp| cp.index).collect::<Vec<_>>() == vec![2, 5]);
+    assert!(cps.iter().all(|cp| chain.verify_checkpoint(cp).is_ok()));
+
+    let mut moved = cps[0].clone();
+    moved.index = 3;
+    assert!(chain.verify_checkpoint(&moved).is_err());
+
+    let forged = chain.checkpoints(3, &KeyPair::rand());
+    assert!(chain.verify_checkpoint(&forged[0]).is_err());
+  }
+
+  #[test]
+  fn chain_attestation() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let chain = create_chain_with(&ekp, &skp, 4);
+    let att = chain.attest(&skp).unwrap();
+    assert!(chain.verify_attestation(&att).is_ok());
+
+    for i in 0..4 {
+      let mut altered = chain.clone();
+      altered.chain[i].hprev[0] ^= 1;
+      assert!(altered.verify_attestation(&att).is_err());
+    }
+
+    let mut truncated = chain.clone();
+    truncated.chain.pop();
+    assert!(truncated.verify_attestation(&att).is_err());
+
+    assert!(chain.attest(&KeyPair::rand()).is_err());
+    assert!(create_chain(4).verify_attestation(&att).is_err());
+  }
+
+  #[test]
+  fn chain_verify() {
+    let chain = create_chain(4);
+    assert!(chain.verify().is_ok());
+
+    let mut tampered = chain.clone();
+    tampered.chain[2] = tampered.chain[1].clone();
+    let err = tampered.verify().unwrap_err();
+    assert!(err.to_string().contains("record 2"));
+
+    let mut reordered = chain.clone();
+    reordered.chain.swap(1, 2);
+    let err = reordered.verify().unwrap_err();
+    assert!(err.to_string().contains("record 1"));
+
+    let mut tip = chain.clone();
+    tip.lhash = vec![0u8; 64];
+    assert!(tip.verify().is_err());
+
+    let mut sig = chain;
+    sig.chain[3] = create_chain(4).chain[3].clone();
+    sig.chain[3].hprev = sig.chain[2].id();
+    let err = sig.verify().unwrap_err();
+    assert!(err.to_string().contains("Invalid record 3"));
+  }
+
+  #[test]
+  fn chain_verify_all() {
+    let mut chain = create_chain(6);
+    assert!(chain.verify_all().is_empty());
+
+    let other = create_chain(6);
+    let mut bad = other.chain[1].clone();
+    bad.hprev = chain.chain[0].id();
+    chain.chain[1] = bad;
+    chain.chain[4] = other.chain[4].clone();
+
+    let indexes = chain.verify_all().iter().map(|(i, _)| *i).collect::<Vec<_>>();
+    assert!(indexes == vec![1, 2, 4, 5]);
+  }
+
+  #[test]
+  fn verify_cached() {
+    let mut chain = create_chain(4);
+    let mut cache = VerifyCache::new();
+
+    assert!(chain.verify_cached(&mut cache).is_ok());
+    assert!(cache.checks() == 4);
+
+    assert!(chain.verify_cached(&mut cache).is_ok());
+    assert!(cache.checks() == 4);
+
+    // same content with a signature over other data must not hit the cache
+    let mut forged = chain.clone();
+    let owner = *chain.chain[2].owner();
+    forged.chain[2] = with_signature(&chain.chain[2], ExtSignature { sig: Signature::sign(&KeyPair::rand(), b"other data"), key: owner });
+    assert!(!cache.contains(&forged.chain[2]) && cache.contains(&chain.chain[2]));
+    assert!(forged.verify_cached(&mut cache).is_err());
+    assert!(cache.checks() == 5);
+
+    chain.chain.swap(1, 2);
+    assert!(chain.verify_cached(&mut cache).is_err());
+  }
+
+  #[test]
+  fn chain_verify_suffix() {
+    let chain = create_chain(5);
+    let bridge = vec![chain.chain[1].link(), chain.chain[2].link()];
+    assert!(bridge.iter().zip(chain.chain[1..3].iter()).all(|(link, rn)| link.id() == rn.id()));
+
+    assert!(RecordChain::verify_suffix(&chain.chain[0], &chain.chain[3..], &bridge).is_ok());
+    assert!(RecordChain::verify_suffix(&chain.chain[0], &chain.chain[1..], &[]).is_ok());
+
+    // a corrupt middle or last link breaks the bridge
+    for i in 0..2 {
+      let mut bad = bridge.clone();
+      bad[i].data[0] ^= 1;
+      assert!(RecordChain::verify_suffix(&chain.chain[0], &chain.chain[3..], &bad).is_err());
+    }
+
+    let swapped = vec![bridge[1].clone(), bridge[0].clone()];
+    assert!(RecordChain::verify_suffix(&chain.chain[0], &chain.chain[3..], &swapped).is_err());
+    assert!(RecordChain::verify_suffix(&chain.chain[0], &chain.chain[3..], &bridge[..1]).is_err());
+
+    // the bridge must start at the given head, and the head must be signed
+    let other = create_chain(1);
+    assert!(RecordChain::verify_suffix(&other.chain[0], &chain.chain[3..], &bridge).is_err());
+
+    let head = with_signature(&chain.chain[0], ExtSignature::sign(&KeyPair::rand(), b"other"));
+    assert!(RecordChain::verify_suffix(&head, &chain.chain[3..], &bridge).is_err());
+  }
+
+  #[test]
+  fn first_broken_link() {
+    let mut chain = create_chain(6);
+    assert!(chain.first_broken_link().is_none());
+
+    chain.chain[4].hprev = vec![0u8; 64];
+    assert!(chain.first_broken_link() == Some(4));
+  }
+
+  #[test]
+  fn verify_identity() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let mut chain = create_chain_with(&ekp, &skp, 1);
+    assert!(chain.verify_identity().is_ok());
+
+    let (_, other) = Record::head(&skp, &ekp.key, &salt("other-id", TABLE), RData::head(KeySize::S128, b"file-0"));
+    chain.chain[0] = other.clone();
+    assert!(chain.verify_identity().is_err());
+
+    // re-linking the head to this chain breaks its signature
+    chain.chain[0].hprev = salt(ID, TABLE);
+    assert!(chain.verify_identity().is_err());
+
+    chain.id = "other-id".into();
+    chain.chain[0] = other;
+    assert!(chain.verify_identity().is_ok());
+  }
+
+  #[test]
+  fn chain_ratchet() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+    let salt = salt(ID, TABLE);
+
+    let (l0, head) = Record::head(&skp, &ekp.key, &salt, RData::head(KeySize::S128, b"file-0"));
+    let mut chain = RecordChain::new(ID.into(), TABLE.into(), head).unwrap();
+
+    // ratcheted payloads carry no lprev
+    let mut lambdas = vec![l0];
+    for i in 1..4 {
+      let rd = RData::head(KeySize::S128, format!("file-{}", i).as_bytes());
+      let (ln, tail) = Record::tail_ratchet(&skp, chain.chain.last().unwrap(), &lambdas[i - 1], &salt, rd).unwrap();
+      chain.push(tail).unwrap();
+      lambdas.push(ln);
+    }
+    assert!(chain.verify().is_ok());
+
+    // every key differs and only moves forward
+    for i in 1..lambdas.len() {
+      assert!(lambdas[i] == lambdas[i - 1].ratchet());
+      for j in 0..i {
+        assert!(lambdas[i] != lambdas[j]);
+        assert!(lambdas[i].ratchet() != lambdas[j]);
       }
+    }
+
+    // the master derives every key, and each key decrypts its own record
+    for (i, rn) in chain.chain.iter().enumerate() {
+      assert!(rn.lambda(&ekp.secret, &salt) == lambdas[i]);
+      assert!(rn.data(&lambdas[i], &salt).unwrap().dref.hfile() == format!("file-{}", i).as_bytes());
+    }
+
+    let refs = chain.recover_master(&ekp.secret).unwrap();
+    assert!(refs.len() == 4);
+    assert!(refs[3].hfile() == b"file-3");
+
+    // a later key decrypts its record but leads to no earlier key
+    let rd = chain.chain[2].data(&lambdas[2], &salt).unwrap();
+    assert!(rd.lprev.is_none());
+    assert!(chain.chain[1].data(&lambdas[2], &salt).is_err());
+    assert!(chain.recover_from(lambdas[3].clone()).is_err());
+
+    // ratcheted records reject an lprev and a wrong previous lambda
+    let last = chain.chain.last().unwrap();
+    assert!(Record::tail_ratchet(&skp, last, &lambdas[3], &salt, RData::tail(KeySize::S128, lambdas[3].clone(), b"file-4")).is_err());
+    assert!(Record::tail_ratchet(&skp, last, &lambdas[2], &salt, RData::head(KeySize::S128, b"file-4")).is_err());
+  }
+
+  #[test]
+  fn compromised_ephemeral() {
+    let chain = create_chain(4);
+
+    let mut bad = HashSet::new();
+    assert!(chain.contains_compromised_ephemeral(&bad).is_empty());
+
+    bad.insert(chain.chain[2].ephemeral_key().to_bytes());
+    bad.insert(KeyPair::rand().key.to_bytes());
+    assert!(chain.contains_compromised_ephemeral(&bad) == vec![2]);
+  }
+
+  #[test]
+  fn nonce_reuse() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let mut chain = create_chain_with(&ekp, &skp, 4);
+    assert!(chain.detect_nonce_reuse().is_empty());
+
+    // re-sign record 3 with the nonce of record 1, m = p1 + c1 * x
+    let first = &chain.chain[1].signature().sig;
+    let c1 = Signature::challenge(&skp.key, &first.M, &chain.chain[1].id());
+    let nonce = &first.p + &c1 * &skp.secret;
+    let commit = first.M;
+
+    let dhash = chain.chain[3].id();
+    let c = Signature::challenge(&skp.key, &commit, &dhash);
+    let p = &nonce - &c * &skp.secret;
+    chain.chain[3] = with_signature(&chain.chain[3], ExtSignature { sig: Signature { M: commit, p }, key: skp.key });
+
+    assert!(chain.chain[3].check().is_ok());
+    assert!(chain.detect_nonce_reuse() == vec![(1, 3)]);
+  }
+
+  #[test]
+  fn migrate_salt() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let chain = create_chain_with(&ekp, &skp, 3);
+    let migrated = chain.migrate_salt(&ekp.secret, &skp, "new-subject-id").unwrap();
+    assert!(migrated.id == "new-subject-id");
+    assert!(migrated.chain.len() == 3);
+
+    let old_salt = salt(ID, TABLE);
+    let new_salt = salt("new-subject-id", TABLE);
+
+    let mut lprev: Option<LambdaKey> = None;
+    for (old, new) in chain.chain.iter().zip(migrated.chain.iter()) {
+      let lambda = new.lambda(&ekp.secret, &new_salt);
+      let rd = new.data(&lambda, &new_salt).unwrap();
+      assert!(rd.lprev == lprev);
+      assert!(rd.dref == old.data(&old.lambda(&ekp.secret, &old_salt), &old_salt).unwrap().dref);
+      assert!(new.data(&new.lambda(&ekp.secret, &old_salt), &new_salt).is_err());
+      lprev = Some(lambda);
+    }
+  }
+
+  #[test]
+  fn chain_serialized_size() {
+    let chain = create_chain(3);
+    let data = bincode::serialize(&chain).unwrap();
+    assert!(chain.serialized_size().unwrap() == data.len() as u64);
+  }
+
+  #[test]
+  fn encrypted_refs() {
+    let chain = create_chain(4);
+    let refs = chain.encrypted_refs();
+
+    assert!(refs.len() == chain.chain.len());
+    assert!(refs.iter().all(|r| !r.is_empty()));
+  }
+
+  #[test]
+  fn push_head_as_tail() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let mut chain = create_chain_with(&ekp, &skp, 2);
+    let (_, head) = Record::head(&skp, &ekp.key, &salt(ID, TABLE), RData::head(KeySize::S128, b"file-0"));
+
+    let err = chain.push(head).err().unwrap();
+    assert!(err.to_string() == "Attempted to push a head record as tail!");
+  }
+
+  #[test]
+  fn push_unchecked() {
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+
+    let mut chain = create_chain_with(&ekp, &skp, 1);
+    let lambda = chain.chain[0].lambda(&ekp.secret, &salt(ID, TABLE));
+    let (_, tail) = Record::tail(&skp, &ekp.key, &chain.lhash, &salt(ID, TABLE), RData::tail(KeySize::S128, lambda, b"file-1"));
+
+    // a valid signature of the owner, but over different data
+    let forged = with_signature(&tail, ExtSignature::sign(&skp, b"other data"));
+
+    assert!(forged.check().is_err());
+    assert!(chain.push(forged.clone()).is_err());
+    assert!(chain.push_unchecked(forged).is_ok());
+    assert!(chain.chain.len() == 2);
+  }
+
+  #[test]
+  fn push_if_owner() {
+    let salt = salt(ID, TABLE);
+
+    let ekp = KeyPair::rand();
+    let skp = KeyPair::rand();
+    let other = KeyPair::rand();
+
+    let mut allowed = HashSet::new();
+    allowed.insert(skp.key.to_bytes());
+
+    let (lambda, head) = Record::head(&skp, &ekp.key, &salt, RData::head(KeySize::S128, b"file-0"));
+    let mut chain = RecordChain::new(ID.into(), TABLE.into(), head).unwrap();
+
+    let rd = RData::tail(KeySize::S128, lambda.clone(), b"file-1");
+    let (_, tail) = Record::tail(&other, &ekp.key, &chain.lhash, &salt, rd);
+    assert!(chain.push_if_owner(tail, &allowed).is_err());
+
+    let rd = RData::tail(KeySize::S128, lambda, b"file-1");
+    let (_, tail) = Record::tail(&skp, &ekp.key, &chain.lhash, &salt, rd);
+    assert!(chain.push_if_owner(tail, &allowed).is_ok());
+    assert!(chain.chain.len() == 2);
+  }
+
+  #[test]
+  fn verify_at_correct_index() {
+    let chain = create_chain(3);
+    for (i, record) in chain.chain.iter().enumerate() {
+      assert!(chain.verify_at(i, record).is_ok());
+    }
+  }
+
+  #[test]
+  fn verify_at_wrong_index() {
+    let chain = create_chain(3);
+    assert!(chain.verify_at(2, &chain.chain[1]).is_err());
+    assert!(chain.verify_at(0, &chain.chain[1]).is_err());
+  }
 
-      chain.reverse();
-      Ok(chain)
+  #[test]
+  fn verify_at_out_of_bounds() {
+    let chain = create_chain(3);
+    assert!(chain.verify_at(3, &chain.chain[2]).is_err());
   }
 }
@@ -0,0 +1,103 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use fdc_core::{error, Result};
+use fdc_core::model::*;
+
+use crate::RecordChain;
+use crate::codec::{decode_record, encode_record, read_frame, write_frame};
+
+//-----------------------------------------------------------------------------------------------------------
+// ChainLog (append-only file: [len | (id, table)] followed by [len | Record] frames)
+//-----------------------------------------------------------------------------------------------------------
+pub struct ChainLog {
+  path: PathBuf,
+  id: String,
+  table: String,
+
+  lhash: Option<Vec<u8>> // last stored Record hash
+}
+
+impl ChainLog {
+  pub fn create<P: AsRef<Path>>(path: P, id: &str, table: &str) -> Result<Self> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+    write_frame(&mut file, &bincode::serialize(&(id, table))?)?;
+
+    Ok(Self { path: path.as_ref().into(), id: id.into(), table: table.into(), lhash: None })
+  }
+
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let mut from = BufReader::new(File::open(&path)?);
+    let header = read_frame(&mut from)?.ok_or_else(|| error("ChainLog: Missing header!"))?;
+    let (id, table): (String, String) = bincode::deserialize(&header)?;
+
+    let mut lhash = None;
+    while let Some(record) = decode_record(&mut from)? {
+      lhash = Some(record.id());
+    }
+
+    Ok(Self { path: path.as_ref().into(), id, table, lhash })
+  }
+
+  pub fn append(&mut self, record: &Record) -> Result<()> {
+    let dhash = record.check()?;
+    let hprev = match &self.lhash {
+      None => salt(&self.id, &self.table),
+      Some(lhash) => lhash.clone()
+    };
+
+    if record.hprev != hprev {
+      Err("Incorrect hash chain!")?
+    }
+
+    let mut file = OpenOptions::new().append(true).open(&self.path)?;
+    file.write_all(&encode_record(record)?)?;
+
+    self.lhash = Some(dhash);
+    Ok(())
+  }
+
+  pub fn load(&self) -> Result<RecordChain> {
+    let mut from = BufReader::new(File::open(&self.path)?);
+    read_frame(&mut from)?.ok_or_else(|| error("ChainLog: Missing header!"))?;
+
+    let head = decode_record(&mut from)?.ok_or_else(|| error("ChainLog: No records!"))?;
+    let mut chain = RecordChain::new(self.id.clone(), self.table.clone(), head)?;
+    while let Some(record) = decode_record(&mut from)? {
+      chain.push(record)?;
+    }
+
+    Ok(chain)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tests::create_chain;
+
+  #[test]
+  fn append_and_load() {
+    let chain = create_chain(3);
+
+    let name = fdc_core::rand(8).iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let path = std::env::temp_dir().join(format!("fdc-chain-{}.log", name));
+    let mut log = ChainLog::create(&path, &chain.id, &chain.table).unwrap();
+
+    assert!(log.append(&chain.chain[1]).is_err());
+    for record in chain.chain.iter() {
+      log.append(record).unwrap();
+    }
+
+    let mut log = ChainLog::open(&path).unwrap();
+    assert!(log.append(&chain.chain[2]).is_err());
+
+    let loaded = log.load().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(loaded.lhash == chain.lhash);
+    assert!(loaded.chain.len() == 3);
+    assert!(loaded.first_broken_link().is_none());
+  }
+}
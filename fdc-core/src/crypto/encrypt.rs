@@ -1,57 +1,626 @@
-use crypto::aes::KeySize;
+use crypto::aes;
 use crypto::aesni::{AesNiEncryptor, AesNiDecryptor};
+use crypto::chacha20::ChaCha20;
+use crypto::poly1305::Poly1305;
+use crypto::symmetriccipher::{BlockEncryptor, BlockDecryptor, SynchronousStreamCipher};
+use crypto::mac::Mac;
 use aesstream::{AesWriter, AesReader};
+use serde::{Serialize, Deserialize};
 
-use std::io::{Read, Write};
+use std::io::{Read, Write, Cursor, Error, ErrorKind};
 
 use crate::Result;
-use crate::crypto::LambdaKey;
+use crate::crypto::{KeySize, LambdaKey};
 
 //-----------------------------------------------------------------------------------------------------------
 // Supported encryption schemes
 //-----------------------------------------------------------------------------------------------------------
-#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum EncryptScheme {
-  AesCbc128, AesCbc192, AesCbc256
+  AesCbc128, AesCbc192, AesCbc256, XChaCha20Poly1305
+}
+
+impl EncryptScheme {
+  pub fn supports(&self, ksize: &KeySize) -> bool {
+    match self {
+      EncryptScheme::AesCbc128 => *ksize == KeySize::S128,
+      EncryptScheme::AesCbc192 => *ksize == KeySize::S192,
+      EncryptScheme::AesCbc256 => *ksize == KeySize::S256,
+      EncryptScheme::XChaCha20Poly1305 => *ksize == KeySize::S512
+    }
+  }
+
+  pub fn tag(&self) -> u8 {
+    match self {
+      EncryptScheme::AesCbc128 => 0,
+      EncryptScheme::AesCbc192 => 1,
+      EncryptScheme::AesCbc256 => 2,
+      EncryptScheme::XChaCha20Poly1305 => 3
+    }
+  }
+
+  pub fn is_aead(&self) -> bool {
+    matches!(self, EncryptScheme::XChaCha20Poly1305)
+  }
+
+  pub fn ksize(&self) -> KeySize {
+    match self {
+      EncryptScheme::AesCbc128 => KeySize::S128,
+      EncryptScheme::AesCbc192 => KeySize::S192,
+      EncryptScheme::AesCbc256 => KeySize::S256,
+      EncryptScheme::XChaCha20Poly1305 => KeySize::S512
+    }
+  }
 }
 
 //-----------------------------------------------------------------------------------------------------------
 // encryptor / decryptor
 //-----------------------------------------------------------------------------------------------------------
-pub fn encryptor<'a, W: Write + 'a>(scheme: EncryptScheme, key: &LambdaKey, to: W) -> Result<Box<dyn Write + 'a>> {
-  let engine = match scheme {
+fn check(scheme: EncryptScheme, ksize: &KeySize, aad: &[u8]) -> Result<()> {
+  if !scheme.supports(ksize) {
+    Err("Key size not supported by the encryption scheme!")?
+  }
+
+  if !aad.is_empty() && !scheme.is_aead() {
+    Err("Associated data requires an AEAD scheme!")?
+  }
+
+  Ok(())
+}
+
+// the last block (CBC padding or AEAD tag) is only written by finish(), so write errors are not lost on drop
+pub trait Encryptor: Write {
+  fn finish(self: Box<Self>) -> Result<()>;
+}
+
+impl<E: BlockEncryptor, W: Write> Encryptor for AesWriter<E, W> {
+  fn finish(mut self: Box<Self>) -> Result<()> {
+    // aesstream pads and closes the stream on flush
+    self.flush()?;
+    Ok(())
+  }
+}
+
+// aad is authenticated but not encrypted, only AEAD schemes accept it
+pub fn encryptor<'a, W: Write + 'a>(scheme: EncryptScheme, ksize: &KeySize, key: &LambdaKey, aad: &[u8], to: W) -> Result<Box<dyn Encryptor + 'a>> {
+  check(scheme, ksize, aad)?;
+
+  let engine: Box<dyn Encryptor + 'a> = match scheme {
     EncryptScheme::AesCbc128 => {
-      let encryptor = AesNiEncryptor::new(KeySize::KeySize128, key.k128());
+      let encryptor = AesNiEncryptor::new(aes::KeySize::KeySize128, key.k128()?);
       Box::new(AesWriter::new(to, encryptor)?)
     },
     EncryptScheme::AesCbc192 => {
-      let encryptor = AesNiEncryptor::new(KeySize::KeySize192, key.k192());
+      let encryptor = AesNiEncryptor::new(aes::KeySize::KeySize192, key.k192()?);
       Box::new(AesWriter::new(to, encryptor)?)
     },
     EncryptScheme::AesCbc256 => {
-      let encryptor = AesNiEncryptor::new(KeySize::KeySize256, key.k256());
+      let encryptor = AesNiEncryptor::new(aes::KeySize::KeySize256, key.k256()?);
       Box::new(AesWriter::new(to, encryptor)?)
-    }
+    },
+    EncryptScheme::XChaCha20Poly1305 => Box::new(XChaChaWriter::new(key, aad, to)?)
   };
 
   Ok(engine)
 }
 
-pub fn decryptor<'a, R: Read + 'a>(scheme: EncryptScheme, key: &LambdaKey, from: R) -> Result<Box<dyn Read + 'a>> {
-  let engine = match scheme {
+pub fn decryptor<'a, R: Read + 'a>(scheme: EncryptScheme, ksize: &KeySize, key: &LambdaKey, aad: &[u8], from: R) -> Result<Box<dyn Read + 'a>> {
+  check(scheme, ksize, aad)?;
+
+  let engine: Box<dyn Read + 'a> = match scheme {
     EncryptScheme::AesCbc128 => {
-      let decryptor = AesNiDecryptor::new(KeySize::KeySize128, key.k128());
-      Box::new(AesReader::new(from, decryptor)?)
+      let decryptor = AesNiDecryptor::new(aes::KeySize::KeySize128, key.k128()?);
+      Box::new(CbcReader::new(from, decryptor)?)
     },
     EncryptScheme::AesCbc192 => {
-      let decryptor = AesNiDecryptor::new(KeySize::KeySize192, key.k192());
-      Box::new(AesReader::new(from, decryptor)?)
+      let decryptor = AesNiDecryptor::new(aes::KeySize::KeySize192, key.k192()?);
+      Box::new(CbcReader::new(from, decryptor)?)
     },
     EncryptScheme::AesCbc256 => {
-      let decryptor = AesNiDecryptor::new(KeySize::KeySize256, key.k256());
-      Box::new(AesReader::new(from, decryptor)?)
-    }
+      let decryptor = AesNiDecryptor::new(aes::KeySize::KeySize256, key.k256()?);
+      Box::new(CbcReader::new(from, decryptor)?)
+    },
+    EncryptScheme::XChaCha20Poly1305 => Box::new(XChaChaReader::new(key, aad, from)?)
   };
 
   Ok(engine)
-}
\ No newline at end of file
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// CBC reader (aesstream reports truncated blocks and bad padding as generic errors)
+//-----------------------------------------------------------------------------------------------------------
+fn invalid_ciphertext(e: Error) -> Error {
+  match e.kind() {
+    ErrorKind::Other | ErrorKind::UnexpectedEof => Error::new(ErrorKind::InvalidData, "Decryption error: invalid ciphertext length/padding"),
+    _ => e
+  }
+}
+
+struct CbcReader<D: BlockDecryptor, R: Read>(AesReader<D, R>);
+
+impl<D: BlockDecryptor, R: Read> CbcReader<D, R> {
+  fn new(from: R, decryptor: D) -> std::io::Result<Self> {
+    Ok(Self(AesReader::new(from, decryptor).map_err(invalid_ciphertext)?))
+  }
+}
+
+impl<D: BlockDecryptor, R: Read> Read for CbcReader<D, R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.0.read(buf).map_err(invalid_ciphertext)
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// file streams (fixed-size chunks, memory use is bounded for every scheme)
+//-----------------------------------------------------------------------------------------------------------
+const CHUNK_SIZE: usize = 64 * 1024;
+
+struct CountingWriter<W: Write> {
+  to: W,
+  count: u64
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    let n = self.to.write(buf)?;
+    self.count += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.to.flush()
+  }
+}
+
+fn copy_chunks<R: Read, W: Write>(from: &mut R, to: &mut W) -> Result<()> {
+  let mut chunk = vec![0u8; CHUNK_SIZE];
+  loop {
+    let n = match from.read(&mut chunk) {
+      Ok(0) => return Ok(()),
+      Ok(n) => n,
+      Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+      Err(e) => Err(e)?
+    };
+
+    to.write_all(&chunk[..n])?;
+  }
+}
+
+// returns the number of ciphertext bytes written
+pub fn encrypt_file<R: Read, W: Write>(scheme: EncryptScheme, key: &LambdaKey, mut from: R, to: W) -> Result<u64> {
+  let mut counter = CountingWriter { to, count: 0 };
+  let mut enc = encryptor(scheme, &scheme.ksize(), key, &[], &mut counter)?;
+  copy_chunks(&mut from, &mut enc)?;
+  enc.finish()?;
+
+  counter.flush()?;
+  Ok(counter.count)
+}
+
+// returns the number of plaintext bytes written
+pub fn decrypt_file<R: Read, W: Write>(scheme: EncryptScheme, key: &LambdaKey, from: R, to: W) -> Result<u64> {
+  let mut counter = CountingWriter { to, count: 0 };
+  let mut dec = decryptor(scheme, &scheme.ksize(), key, &[], from)?;
+  copy_chunks(&mut dec, &mut counter)?;
+
+  counter.flush()?;
+  Ok(counter.count)
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// XChaCha20-Poly1305 (STREAM construction, every CHUNK_SIZE plaintext chunk is sealed on its own)
+//-----------------------------------------------------------------------------------------------------------
+const NONCE_SIZE: usize = 24;
+const PREFIX_SIZE: usize = 19;
+const TAG_SIZE: usize = 16;
+
+// the cipher key is k256() of the LambdaKey, the 192-bit nonce is random and prefixed to the ciphertext
+fn xchacha20(key: &[u8; 32], nonce: &[u8], aad: &[u8]) -> (ChaCha20, Poly1305) {
+  let mut cipher = ChaCha20::new_xchacha20(key, nonce);
+
+  // the first keystream block is the one-time poly1305 key
+  let mut block = [0u8; 64];
+  cipher.process(&[0u8; 64], &mut block);
+
+  let mut mac = Poly1305::new(&block[..32]);
+  mac.input(aad);
+  mac.input(&[0u8; 16][..padding(aad.len())]);
+  (cipher, mac)
+}
+
+fn padding(len: usize) -> usize {
+  (16 - len % 16) % 16
+}
+
+fn xchacha20_tag(mut mac: Poly1305, aad_len: usize, ciphertext: &[u8]) -> [u8; TAG_SIZE] {
+  mac.input(ciphertext);
+  mac.input(&[0u8; 16][..padding(ciphertext.len())]);
+  mac.input(&(aad_len as u64).to_le_bytes());
+  mac.input(&(ciphertext.len() as u64).to_le_bytes());
+
+  let mut tag = [0u8; TAG_SIZE];
+  mac.raw_result(&mut tag);
+  tag
+}
+
+// returns ciphertext || tag
+fn seal(key: &[u8; 32], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+  let (mut cipher, mac) = xchacha20(key, nonce, aad);
+  let mut sealed = vec![0u8; plaintext.len()];
+  cipher.process(plaintext, &mut sealed);
+
+  let tag = xchacha20_tag(mac, aad.len(), &sealed);
+  sealed.extend_from_slice(&tag);
+  sealed
+}
+
+fn open(key: &[u8; 32], nonce: &[u8], aad: &[u8], sealed: &[u8]) -> std::io::Result<Vec<u8>> {
+  if sealed.len() < TAG_SIZE {
+    return Err(Error::new(ErrorKind::InvalidData, "Decryption error: invalid ciphertext length/padding"))
+  }
+
+  let (mut cipher, mac) = xchacha20(key, nonce, aad);
+  let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_SIZE);
+  if !crypto::util::fixed_time_eq(&xchacha20_tag(mac, aad.len(), ciphertext), tag) {
+    return Err(Error::new(ErrorKind::InvalidData, "Decryption error: invalid authentication tag"))
+  }
+
+  let mut plaintext = vec![0u8; ciphertext.len()];
+  cipher.process(ciphertext, &mut plaintext);
+  Ok(plaintext)
+}
+
+// nonce = prefix || chunk counter || last flag, so chunks can't be reordered, dropped or appended
+fn chunk_nonce(prefix: &[u8], counter: u32, last: bool) -> Vec<u8> {
+  let mut nonce = Vec::with_capacity(NONCE_SIZE);
+  nonce.extend_from_slice(prefix);
+  nonce.extend_from_slice(&counter.to_be_bytes());
+  nonce.push(last as u8);
+  nonce
+}
+
+struct XChaChaWriter<W: Write> {
+  key: LambdaKey,
+  aad: Vec<u8>,
+  prefix: Vec<u8>,
+  counter: u32,
+  plaintext: Vec<u8>,
+  to: W
+}
+
+impl<W: Write> XChaChaWriter<W> {
+  fn new(key: &LambdaKey, aad: &[u8], to: W) -> Result<Self> {
+    key.k256()?;
+    Ok(Self { key: key.clone(), aad: aad.to_vec(), prefix: crate::rand(PREFIX_SIZE), counter: 0, plaintext: Vec::with_capacity(CHUNK_SIZE), to })
+  }
+
+  fn seal_chunk(&mut self, last: bool) -> std::io::Result<()> {
+    if !last && self.counter == u32::MAX {
+      return Err(Error::new(ErrorKind::InvalidInput, "Encryption error: too many chunks"))
+    }
+
+    if self.counter == 0 {
+      self.to.write_all(&self.prefix)?;
+    }
+
+    let nonce = chunk_nonce(&self.prefix, self.counter, last);
+    let key = self.key.k256().map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    let sealed = seal(key, &nonce, &self.aad, &self.plaintext);
+    self.to.write_all(&sealed)?;
+
+    self.plaintext.clear();
+    self.counter = self.counter.wrapping_add(1);
+    Ok(())
+  }
+}
+
+impl<W: Write> Write for XChaChaWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    // a full chunk is only sealed when more data arrives, the last one is sealed by finish()
+    let mut rest = buf;
+    while !rest.is_empty() {
+      if self.plaintext.len() == CHUNK_SIZE {
+        self.seal_chunk(false)?;
+      }
+
+      let n = std::cmp::min(CHUNK_SIZE - self.plaintext.len(), rest.len());
+      self.plaintext.extend_from_slice(&rest[..n]);
+      rest = &rest[n..];
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl<W: Write> Encryptor for XChaChaWriter<W> {
+  fn finish(mut self: Box<Self>) -> Result<()> {
+    self.seal_chunk(true)?;
+    self.to.flush()?;
+    Ok(())
+  }
+}
+
+struct XChaChaReader<R: Read> {
+  key: LambdaKey,
+  aad: Vec<u8>,
+  prefix: Option<Vec<u8>>,
+  counter: u32,
+  done: bool,
+  lookahead: Option<u8>,
+  plaintext: Cursor<Vec<u8>>,
+  from: R
+}
+
+impl<R: Read> XChaChaReader<R> {
+  fn new(key: &LambdaKey, aad: &[u8], from: R) -> Result<Self> {
+    key.k256()?;
+    Ok(Self { key: key.clone(), aad: aad.to_vec(), prefix: None, counter: 0, done: false, lookahead: None, plaintext: Cursor::new(Vec::new()), from })
+  }
+
+  fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    loop {
+      match self.from.read(&mut byte) {
+        Ok(0) => return Ok(None),
+        Ok(_) => return Ok(Some(byte[0])),
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) => return Err(e)
+      }
+    }
+  }
+
+  // a short chunk, or a full one followed by the end of the stream, is the last chunk
+  fn open_chunk(&mut self) -> std::io::Result<()> {
+    let prefix = match self.prefix.take() {
+      Some(prefix) => prefix,
+      None => {
+        let mut prefix = Vec::with_capacity(PREFIX_SIZE);
+        (&mut self.from).take(PREFIX_SIZE as u64).read_to_end(&mut prefix)?;
+        if prefix.len() < PREFIX_SIZE {
+          return Err(Error::new(ErrorKind::InvalidData, "Decryption error: invalid ciphertext length/padding"))
+        }
+        prefix
+      }
+    };
+
+    let mut sealed = Vec::with_capacity(CHUNK_SIZE + TAG_SIZE);
+    sealed.extend(self.lookahead.take());
+    (&mut self.from).take((CHUNK_SIZE + TAG_SIZE - sealed.len()) as u64).read_to_end(&mut sealed)?;
+
+    let last = sealed.len() < CHUNK_SIZE + TAG_SIZE || {
+      self.lookahead = self.read_byte()?;
+      self.lookahead.is_none()
+    };
+
+    if !last && self.counter == u32::MAX {
+      return Err(Error::new(ErrorKind::InvalidData, "Decryption error: too many chunks"))
+    }
+
+    let nonce = chunk_nonce(&prefix, self.counter, last);
+    let key = self.key.k256().map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    self.plaintext = Cursor::new(open(key, &nonce, &self.aad, &sealed)?);
+    self.prefix = Some(prefix);
+    self.counter = self.counter.wrapping_add(1);
+    self.done = last;
+    Ok(())
+  }
+}
+
+impl<R: Read> Read for XChaChaReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+      let n = self.plaintext.read(buf)?;
+      if n > 0 || buf.is_empty() || self.done {
+        return Ok(n)
+      }
+
+      self.open_chunk()?;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::crypto::G;
+
+  #[test]
+  fn test_supports() {
+    assert!(EncryptScheme::AesCbc128.supports(&KeySize::S128));
+    assert!(EncryptScheme::AesCbc256.supports(&KeySize::S256));
+    assert!(!EncryptScheme::AesCbc128.supports(&KeySize::S512));
+  }
+
+  #[test]
+  fn test_unsupported_ksize() {
+    let key = LambdaKey::new(&G, b"salt");
+
+    let mut to = Vec::new();
+    assert!(encryptor(EncryptScheme::AesCbc128, &KeySize::S512, &key, &[], &mut to).is_err());
+    assert!(decryptor(EncryptScheme::AesCbc128, &KeySize::S512, &key, &[], to.as_slice()).is_err());
+  }
+
+  #[test]
+  fn test_truncated_ciphertext() {
+    let key = LambdaKey::new(&G, b"salt");
+    let decrypt = |scheme: EncryptScheme, data: &[u8]| match decryptor(scheme, &scheme.ksize(), &key, &[], data) {
+      Ok(mut dec) => dec.read_to_end(&mut Vec::new()).unwrap_err().to_string(),
+      Err(e) => e.to_string()
+    };
+
+    for scheme in [EncryptScheme::AesCbc128, EncryptScheme::AesCbc256, EncryptScheme::XChaCha20Poly1305].iter() {
+      let mut to = Vec::new();
+      let mut enc = encryptor(*scheme, &scheme.ksize(), &key, &[], &mut to).unwrap();
+      enc.write_all(b"some data to truncate").unwrap();
+      enc.finish().unwrap();
+
+      for cut in [3, to.len() - 4, to.len()].iter() {
+        let err = decrypt(*scheme, &to[..to.len() - cut]);
+        match scheme {
+          EncryptScheme::XChaCha20Poly1305 => assert!(err.starts_with("Decryption error: ")),
+          _ => assert!(err == "Decryption error: invalid ciphertext length/padding")
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_file_round_trip() {
+    let key = LambdaKey::new(&G, b"salt");
+    let data = crate::rand(3 * 1024 * 1024 + 17);
+
+    for scheme in [EncryptScheme::AesCbc256, EncryptScheme::XChaCha20Poly1305].iter() {
+      let mut encrypted = Vec::new();
+      let written = encrypt_file(*scheme, &key, data.as_slice(), &mut encrypted).unwrap();
+      assert!(written == encrypted.len() as u64);
+      assert!(encrypted.len() > data.len());
+
+      let mut decrypted = Vec::new();
+      let written = decrypt_file(*scheme, &key, encrypted.as_slice(), &mut decrypted).unwrap();
+      assert!(written == data.len() as u64);
+      assert!(decrypted == data);
+    }
+  }
+
+  #[test]
+  fn test_xchacha20poly1305() {
+    let key = LambdaKey::new(&G, b"salt");
+    let data = b"some data that is not a multiple of the block size".to_vec();
+    assert!(EncryptScheme::XChaCha20Poly1305.supports(&KeySize::S512));
+    assert!(!EncryptScheme::XChaCha20Poly1305.supports(&KeySize::S256));
+
+    let encrypt = || {
+      let mut to = Vec::new();
+      let mut enc = encryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], &mut to).unwrap();
+      enc.write_all(&data).unwrap();
+      enc.finish().unwrap();
+      to
+    };
+
+    // the nonce is random, so the same key and plaintext never repeat a keystream
+    let mut to = encrypt();
+    assert!(to.len() == PREFIX_SIZE + data.len() + TAG_SIZE);
+    assert!(to != encrypt());
+
+    let mut plain = Vec::new();
+    let mut dec = decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], to.as_slice()).unwrap();
+    dec.read_to_end(&mut plain).unwrap();
+    assert!(plain == data);
+    drop(dec);
+
+    // tampered ciphertext and wrong keys are rejected
+    to[3] ^= 1;
+    let mut dec = decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], to.as_slice()).unwrap();
+    assert!(dec.read_to_end(&mut Vec::new()).is_err());
+    drop(dec);
+
+    to[3] ^= 1;
+    let other = LambdaKey::new(&G, b"other-salt");
+    let mut dec = decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &other, &[], to.as_slice()).unwrap();
+    assert!(dec.read_to_end(&mut Vec::new()).is_err());
+  }
+
+  #[test]
+  fn test_xchacha20poly1305_known_answer() {
+    // AEAD test vector of draft-irtf-cfrg-xchacha-03 (A.3.1), HChaCha20 subkey + ChaCha20-Poly1305
+    let key = (0x80..0xa0).collect::<Vec<u8>>();
+    let nonce = (0x40..0x58).collect::<Vec<u8>>();
+    let aad = [0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7];
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+    let key = arrayref::array_ref!(key, 0, 32);
+    let sealed = seal(key, &nonce, &aad, plaintext);
+    assert!(open(key, &nonce, &aad, &sealed).unwrap() == plaintext.to_vec());
+
+    let hex = sealed.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    assert!(hex == concat!(
+      "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb",
+      "731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452",
+      "2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9",
+      "21f9664c97637da9768812f615c68b13b52e",
+      "c0875924c1c7987947deafd8780acf49"
+    ));
+  }
+
+  #[test]
+  fn test_associated_data() {
+    let key = LambdaKey::new(&G, b"salt");
+
+    let mut to = Vec::new();
+    let mut enc = encryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, b"context", &mut to).unwrap();
+    enc.write_all(b"data").unwrap();
+    enc.finish().unwrap();
+
+    let mut plain = Vec::new();
+    decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, b"context", to.as_slice()).unwrap()
+      .read_to_end(&mut plain).unwrap();
+    assert!(plain == b"data");
+
+    let mut dec = decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, b"other", to.as_slice()).unwrap();
+    assert!(dec.read_to_end(&mut Vec::new()).is_err());
+
+    assert!(encryptor(EncryptScheme::AesCbc128, &KeySize::S128, &key, b"context", Vec::new()).is_err());
+  }
+
+  #[test]
+  fn test_unfinished_encryptor() {
+    let key = LambdaKey::new(&G, b"salt");
+
+    // dropping without finish() leaves no decryptable output behind
+    let mut to = Vec::new();
+    let mut enc = encryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], &mut to).unwrap();
+    enc.write_all(b"data").unwrap();
+    drop(enc);
+
+    let mut dec = decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], to.as_slice()).unwrap();
+    assert!(dec.read_to_end(&mut Vec::new()).is_err());
+  }
+
+  #[test]
+  fn test_xchacha20poly1305_chunks() {
+    let key = LambdaKey::new(&G, b"salt");
+    let encrypt = |data: &[u8]| {
+      let mut to = Vec::new();
+      let mut enc = encryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], &mut to).unwrap();
+      enc.write_all(data).unwrap();
+      enc.finish().unwrap();
+      to
+    };
+
+    let decrypt = |data: &[u8]| {
+      let mut plain = Vec::new();
+      decryptor(EncryptScheme::XChaCha20Poly1305, &KeySize::S512, &key, &[], data).unwrap()
+        .read_to_end(&mut plain).map(|_| plain)
+    };
+
+    // empty and exact multiples of the chunk size still end with a last chunk
+    for size in [0, 100, CHUNK_SIZE, 2 * CHUNK_SIZE, 2 * CHUNK_SIZE + 1].iter() {
+      let data = crate::rand(*size);
+      let to = encrypt(&data);
+      let chunks = std::cmp::max(1, size.div_ceil(CHUNK_SIZE));
+      assert!(to.len() == PREFIX_SIZE + size + chunks * TAG_SIZE);
+      assert!(decrypt(&to).unwrap() == data);
+    }
+
+    let data = crate::rand(3 * CHUNK_SIZE);
+    let to = encrypt(&data);
+    let sealed = CHUNK_SIZE + TAG_SIZE;
+
+    // dropping the last chunk, reordering or appending chunks is rejected
+    assert!(decrypt(&to[..PREFIX_SIZE + 2 * sealed]).is_err());
+
+    let mut swapped = to[..PREFIX_SIZE].to_vec();
+    swapped.extend_from_slice(&to[PREFIX_SIZE + sealed..PREFIX_SIZE + 2 * sealed]);
+    swapped.extend_from_slice(&to[PREFIX_SIZE..PREFIX_SIZE + sealed]);
+    swapped.extend_from_slice(&to[PREFIX_SIZE + 2 * sealed..]);
+    assert!(decrypt(&swapped).is_err());
+
+    let mut appended = to.clone();
+    appended.extend_from_slice(&to[PREFIX_SIZE..PREFIX_SIZE + sealed]);
+    assert!(decrypt(&appended).is_err());
+  }
+}
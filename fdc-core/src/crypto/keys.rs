@@ -1,15 +1,19 @@
 use clear_on_drop::clear::Clear;
+use subtle::{Choice, ConditionallySelectable};
 
 use std::fmt::{Debug, Formatter};
+use std::collections::HashSet;
+use std::iter::FromIterator;
 use serde::{Serialize, Deserialize};
 use core::ops::{Neg, Add, Mul, Sub, AddAssign, MulAssign, SubAssign};
 
-use sha2::Sha512;
+use sha2::{Sha256, Sha512};
 use digest::generic_array::typenum::U64;
 use digest::Digest;
 
 use curve25519_dalek::ristretto::{RistrettoPoint, CompressedRistretto};
 use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
 
 use crate::{error, Result};
 
@@ -22,12 +26,37 @@ fn rand_scalar() -> Scalar {
   Scalar::from_bytes_mod_order_wide(&scalar_bytes)
 }
 
+const PASSPHRASE_ROUNDS: u32 = 100_000;
+
+fn hkdf_sha512(ikm: &[u8], salt: &[u8], info: &[u8]) -> Vec<u8> {
+  use crypto::hkdf::{hkdf_extract, hkdf_expand};
+  use crypto::sha2::Sha512;
+
+  let mut prk = [0u8; 64];
+  hkdf_extract(Sha512::new(), salt, ikm, &mut prk);
+
+  let mut okm = vec![0u8; 64];
+  hkdf_expand(Sha512::new(), &prk, info, &mut okm);
+  prk.clear();
+
+  okm
+}
+
 pub const G: PublicKey = PublicKey(curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT);
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+// second generator for Pedersen commitments, hashed from the encoding of G so nobody knows log_G(H)
+pub static H: std::sync::LazyLock<PublicKey> = std::sync::LazyLock::new(|| {
+  PublicKey(RistrettoPoint::hash_from_bytes::<Sha512>(&G.to_bytes()))
+});
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum KeySize { S128, S192, S256, S512 }
 
 impl KeySize {
+  pub fn max(a: KeySize, b: KeySize) -> KeySize {
+    Ord::max(a, b)
+  }
+
   pub fn size(&self) -> usize {
     match self {
       KeySize::S128 => 128,
@@ -49,6 +78,15 @@ impl SecretKey {
     SecretKey(rand_scalar())
   }
 
+  pub fn rand_nonzero() -> SecretKey {
+    loop {
+      let secret = rand_scalar();
+      if secret != Scalar::zero() {
+        return SecretKey(secret)
+      }
+    }
+  }
+
   pub fn zero() -> SecretKey {
     SecretKey(Scalar::zero())
   }
@@ -57,6 +95,10 @@ impl SecretKey {
     SecretKey(Scalar::one())
   }
 
+  pub fn sum(keys: &[SecretKey]) -> SecretKey {
+    SecretKey(keys.iter().map(|k| &k.0).sum())
+  }
+
   pub fn invert(&self) -> SecretKey {
     SecretKey(self.0.invert())
   }
@@ -71,12 +113,13 @@ impl SecretKey {
       Err("SecretKey: Decoded value is less than 32 bytes!")?
     }
 
-    let mut bytes = [0u8; 32];
-    bytes.copy_from_slice(&data[0..32]);
+    SecretKey::from_bytes(arrayref::array_ref!(data, 0, 32))
+  }
 
-    let secret = Scalar::from_canonical_bytes(bytes)
+  pub fn from_bytes(bytes: &[u8; 32]) -> Result<SecretKey> {
+    let secret = Scalar::from_canonical_bytes(*bytes)
       .ok_or_else(|| error("SecretKey: Unable to decode Scalar!"))?;
-    
+
     Ok(SecretKey(secret))
   }
 
@@ -89,6 +132,11 @@ impl SecretKey {
     output.copy_from_slice(hash.result().as_slice());
     SecretKey(Scalar::from_bytes_mod_order_wide(&output))
   }
+
+  // constant-time selection, returns a if choice == 0 and b if choice == 1
+  pub fn conditional_select(a: &SecretKey, b: &SecretKey, choice: Choice) -> SecretKey {
+    SecretKey(Scalar::conditional_select(&a.0, &b.0, choice))
+  }
 }
 
 impl Debug for SecretKey {
@@ -118,7 +166,7 @@ impl From<u64> for SecretKey {
 }
 
 neg_variant!(Type = SecretKey);
-impl<'a> Neg for &'a SecretKey {
+impl Neg for &SecretKey {
   type Output = SecretKey;
   fn neg(self) -> SecretKey {
     SecretKey(-self.0)
@@ -126,9 +174,9 @@ impl<'a> Neg for &'a SecretKey {
 }
 
 add_variants!(LHS = SecretKey, RHS = SecretKey, Output = SecretKey);
-impl<'a, 'b> Add<&'b SecretKey> for &'a SecretKey {
+impl Add<&SecretKey> for &SecretKey {
   type Output = SecretKey;
-  fn add(self, rhs: &'b SecretKey) -> SecretKey {
+  fn add(self, rhs: &SecretKey) -> SecretKey {
     SecretKey(self.0 + rhs.0)
   }
 }
@@ -141,9 +189,9 @@ impl<'a> AddAssign<&'a SecretKey> for SecretKey {
 }
 
 sub_variants!(LHS = SecretKey, RHS = SecretKey, Output = SecretKey);
-impl<'a, 'b> Sub<&'b SecretKey> for &'a SecretKey {
+impl Sub<&SecretKey> for &SecretKey {
   type Output = SecretKey;
-  fn sub(self, rhs: &'b SecretKey) -> SecretKey {
+  fn sub(self, rhs: &SecretKey) -> SecretKey {
     SecretKey(self.0 - rhs.0)
   }
 }
@@ -156,9 +204,9 @@ impl<'a> SubAssign<&'a SecretKey> for SecretKey {
 }
 
 mul_variants!(LHS = SecretKey, RHS = SecretKey, Output = SecretKey);
-impl<'a, 'b> Mul<&'b SecretKey> for &'a SecretKey {
+impl Mul<&SecretKey> for &SecretKey {
   type Output = SecretKey;
-  fn mul(self, rhs: &'b SecretKey) -> SecretKey {
+  fn mul(self, rhs: &SecretKey) -> SecretKey {
     SecretKey(self.0 * rhs.0)
   }
 }
@@ -171,24 +219,50 @@ impl<'a> MulAssign<&'a SecretKey> for SecretKey {
 }
 
 mul_variants!(LHS = SecretKey, RHS = PublicKey, Output = PublicKey; Commutative = PublicKey);
-impl<'a, 'b> Mul<&'b PublicKey> for &'a SecretKey {
+impl Mul<&PublicKey> for &SecretKey {
   type Output = PublicKey;
-  fn mul(self, rhs: &'b PublicKey) -> PublicKey {
-    PublicKey(self.0 * &rhs.0)
+  fn mul(self, rhs: &PublicKey) -> PublicKey {
+    PublicKey(self.0 * rhs.0)
   }
 }
 
 //-----------------------------------------------------------------------------------------------------------
 // PublicKey
 //-----------------------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize, Copy, Clone, Eq, PartialEq)]
 pub struct PublicKey(RistrettoPoint);
 
+// same encoding as the derived impl, but the identity is rejected like in from_bytes
+impl<'de> Deserialize<'de> for PublicKey {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename = "PublicKey")]
+    struct Encoded(RistrettoPoint);
+
+    let Encoded(key) = Encoded::deserialize(deserializer)?;
+    if key.is_identity() {
+      return Err(serde::de::Error::custom("PublicKey: Identity point is not a valid key!"))
+    }
+
+    Ok(PublicKey(key))
+  }
+}
+
 impl PublicKey {
   pub fn zero() -> PublicKey {
     PublicKey(RistrettoPoint::default())
   }
 
+  // the identity for an empty slice
+  pub fn sum(keys: &[PublicKey]) -> PublicKey {
+    PublicKey(keys.iter().map(|k| &k.0).sum())
+  }
+
+  // sum of scalars[i] * points[i] in variable time, only for public inputs like signature verification
+  pub fn vartime_multiscalar_mul(scalars: &[SecretKey], points: &[PublicKey]) -> PublicKey {
+    PublicKey(RistrettoPoint::vartime_multiscalar_mul(scalars.iter().map(|s| &s.0), points.iter().map(|p| &p.0)))
+  }
+
   pub fn encode(&self) -> String {
     base64::encode(&self.to_bytes())
   }
@@ -199,19 +273,44 @@ impl PublicKey {
       Err("PublicKey: Decoded value is less than 32 bytes!")?
     }
 
-    let mut bytes = [0u8; 32];
-    bytes.copy_from_slice(&data[0..32]);
+    PublicKey::from_bytes(arrayref::array_ref!(data, 0, 32))
+  }
 
-    let key = CompressedRistretto(bytes).decompress()
+  pub fn decode_many(values: &[&str]) -> Result<Vec<PublicKey>> {
+    values.iter().enumerate()
+      .map(|(i, value)| PublicKey::decode(value).map_err(|e| error(&format!("PublicKey: Invalid key at index {}: {}", i, e))))
+      .collect()
+  }
+
+  pub fn from_bytes(bytes: &[u8; 32]) -> Result<PublicKey> {
+    let key = CompressedRistretto(*bytes).decompress()
       .ok_or_else(|| error("PublicKey: Unable to decompress RistrettoPoint!"))?;
-    
+
+    if key.is_identity() {
+      Err("PublicKey: Identity point is not a valid key!")?
+    }
+
     Ok(PublicKey(key))
   }
 
+  pub fn is_identity(&self) -> bool {
+    self.0.is_identity()
+  }
+
   pub fn to_bytes(&self) -> [u8; 32] {
     let compressed = self.0.compress();
     compressed.to_bytes()
   }
+
+  // short visual fingerprint, first 4 bytes of SHA-256 over the encoding
+  pub fn fingerprint(&self) -> [u8; 4] {
+    let dhash = Sha256::digest(&self.to_bytes());
+    *arrayref::array_ref!(dhash, 0, 4)
+  }
+
+  pub fn fingerprint_hex(&self) -> String {
+    self.fingerprint().iter().map(|b| format!("{:02x}", b)).collect()
+  }
 }
 
 impl Debug for PublicKey {
@@ -223,9 +322,9 @@ impl Debug for PublicKey {
 }
 
 add_variants!(LHS = PublicKey, RHS = PublicKey, Output = PublicKey);
-impl<'a, 'b> Add<&'b PublicKey> for &'a PublicKey {
+impl Add<&PublicKey> for &PublicKey {
   type Output = PublicKey;
-  fn add(self, rhs: &'b PublicKey) -> PublicKey {
+  fn add(self, rhs: &PublicKey) -> PublicKey {
     PublicKey(self.0 + rhs.0)
   }
 }
@@ -238,9 +337,9 @@ impl<'a> AddAssign<&'a PublicKey> for PublicKey {
 }
 
 sub_variants!(LHS = PublicKey, RHS = PublicKey, Output = PublicKey);
-impl<'a, 'b> Sub<&'b PublicKey> for &'a PublicKey {
+impl Sub<&PublicKey> for &PublicKey {
   type Output = PublicKey;
-  fn sub(self, rhs: &'b PublicKey) -> PublicKey {
+  fn sub(self, rhs: &PublicKey) -> PublicKey {
     PublicKey(self.0 - rhs.0)
   }
 }
@@ -252,6 +351,51 @@ impl<'a> SubAssign<&'a PublicKey> for PublicKey {
   }
 }
 
+//-----------------------------------------------------------------------------------------------------------
+// PublicKeySet (allowlists, revocation lists)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone, Default, Debug, Eq, PartialEq)]
+pub struct PublicKeySet(HashSet<[u8; 32]>);
+
+impl PublicKeySet {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, key: &PublicKey) -> bool {
+    self.0.insert(key.to_bytes())
+  }
+
+  pub fn contains(&self, key: &PublicKey) -> bool {
+    self.0.contains(&key.to_bytes())
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  // for the APIs taking the raw encoded keys
+  pub fn as_bytes_set(&self) -> &HashSet<[u8; 32]> {
+    &self.0
+  }
+}
+
+impl<'a> FromIterator<&'a PublicKey> for PublicKeySet {
+  fn from_iter<I: IntoIterator<Item = &'a PublicKey>>(iter: I) -> Self {
+    Self(iter.into_iter().map(|key| key.to_bytes()).collect())
+  }
+}
+
+impl FromIterator<PublicKey> for PublicKeySet {
+  fn from_iter<I: IntoIterator<Item = PublicKey>>(iter: I) -> Self {
+    Self(iter.into_iter().map(|key| key.to_bytes()).collect())
+  }
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // KeyPair
 //-----------------------------------------------------------------------------------------------------------
@@ -285,7 +429,7 @@ pub struct LambdaKey(Vec<u8>);
 
 impl Drop for LambdaKey {
   fn drop(&mut self) {
-    self.0.clear();
+    self.clear();
   }
 }
 
@@ -299,19 +443,260 @@ impl LambdaKey {
     Self(key)
   }
 
-  pub fn k128(&self) -> &[u8; 16] {
-    arrayref::array_ref!(self.0, 0, 16)
+  // HKDF-SHA512 with info as the context label, independent subkeys from the same alpha
+  pub fn derive(alpha: &PublicKey, salt: &[u8], info: &[u8]) -> Self {
+    Self(hkdf_sha512(&alpha.to_bytes(), salt, info))
+  }
+
+  pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Self {
+    let mut mac = crypto::hmac::Hmac::new(crypto::sha2::Sha512::new(), passphrase.as_bytes());
+    let mut key = vec![0u8; 64];
+    crypto::pbkdf2::pbkdf2(&mut mac, salt, PASSPHRASE_ROUNDS, &mut key);
+
+    Self(key)
+  }
+
+  pub fn derive_subkey(&self, label: &[u8]) -> LambdaKey {
+    Self(hkdf_sha512(&self.0, &[], label))
   }
 
-  pub fn k192(&self) -> &[u8; 24] {
-    arrayref::array_ref!(self.0, 0, 24)
+  // one-way HKDF step, earlier keys can't be derived from a ratcheted one
+  pub fn ratchet(&self) -> LambdaKey {
+    Self(hkdf_sha512(&self.0, &[], b"fdc-lambda-ratchet"))
   }
 
-  pub fn k256(&self) -> &[u8; 32] {
-    arrayref::array_ref!(self.0, 0, 32)
+  // zeroize the key material in place, any further use of the key is an error
+  pub fn clear(&mut self) {
+    Clear::clear(self.0.as_mut_slice());
+    self.0.clear();
+  }
+
+  pub fn is_cleared(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn k128(&self) -> Result<&[u8; 16]> {
+    self.check()?;
+    Ok(arrayref::array_ref!(self.0, 0, 16))
   }
 
-  pub fn k512(&self) -> &[u8; 64] {
-    arrayref::array_ref!(self.0, 0, 64)
+  pub fn k192(&self) -> Result<&[u8; 24]> {
+    self.check()?;
+    Ok(arrayref::array_ref!(self.0, 0, 24))
+  }
+
+  pub fn k256(&self) -> Result<&[u8; 32]> {
+    self.check()?;
+    Ok(arrayref::array_ref!(self.0, 0, 32))
+  }
+
+  pub fn k512(&self) -> Result<&[u8; 64]> {
+    self.check()?;
+    Ok(arrayref::array_ref!(self.0, 0, 64))
+  }
+
+  fn check(&self) -> Result<()> {
+    if self.is_cleared() {
+      Err("LambdaKey: Key material was cleared!")?
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ksize_order() {
+    assert!(KeySize::max(KeySize::S128, KeySize::S256) == KeySize::S256);
+    assert!(KeySize::max(KeySize::S512, KeySize::S192) == KeySize::S512);
+
+    let sizes = [KeySize::S128, KeySize::S192, KeySize::S256, KeySize::S512];
+    for (i, a) in sizes.iter().enumerate() {
+      for (j, b) in sizes.iter().enumerate() {
+        assert!(a.cmp(b) == i.cmp(&j));
+      }
+    }
+  }
+
+  #[test]
+  fn test_conditional_select() {
+    let a = SecretKey::rand();
+    let b = SecretKey::rand();
+
+    assert!(SecretKey::conditional_select(&a, &b, Choice::from(0)) == a);
+    assert!(SecretKey::conditional_select(&a, &b, Choice::from(1)) == b);
+  }
+
+  #[test]
+  fn test_rand_nonzero() {
+    let zero = SecretKey::zero();
+    assert!((0..1000).all(|_| SecretKey::rand_nonzero() != zero));
+  }
+
+  #[test]
+  fn test_from_bytes() {
+    let kp = KeyPair::rand();
+
+    assert!(SecretKey::from_bytes(kp.secret.as_bytes()).unwrap() == kp.secret);
+    assert!(PublicKey::from_bytes(&kp.key.to_bytes()).unwrap() == kp.key);
+    assert!(SecretKey::decode(&kp.secret.encode()).unwrap() == kp.secret);
+    assert!(PublicKey::decode(&kp.key.encode()).unwrap() == kp.key);
+
+    assert!(SecretKey::from_bytes(&[0xff; 32]).is_err());
+    assert!(PublicKey::from_bytes(&[0xff; 32]).is_err());
+  }
+
+  #[test]
+  fn test_reject_identity() {
+    assert!(PublicKey::zero().is_identity());
+    assert!(!G.is_identity());
+
+    assert!(PublicKey::from_bytes(&[0u8; 32]).is_err());
+    assert!(PublicKey::decode(&base64::encode(&[0u8; 32])).is_err());
+    assert!(PublicKey::decode(&PublicKey::zero().encode()).is_err());
+    assert!(PublicKey::decode(&G.encode()).unwrap() == G);
+
+    let encoded = bincode::serialize(&PublicKey::zero()).unwrap();
+    assert!(bincode::deserialize::<PublicKey>(&encoded).is_err());
+
+    let encoded = bincode::serialize(&G).unwrap();
+    assert!(bincode::deserialize::<PublicKey>(&encoded).unwrap() == G);
+  }
+
+  #[test]
+  fn test_decode_many() {
+    let k1 = KeyPair::rand().key.encode();
+    let k2 = KeyPair::rand().key.encode();
+
+    let keys = PublicKey::decode_many(&[&k1, &k2]).unwrap();
+    assert!(keys.len() == 2 && keys[1].encode() == k2);
+
+    let err = PublicKey::decode_many(&[&k1, "not-a-key!", &k2]).unwrap_err();
+    assert!(err.to_string().contains("index 1"));
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_sum() {
+    let secrets = (0..5).map(|_| SecretKey::rand()).collect::<Vec<_>>();
+    let keys = secrets.iter().map(|s| s * G).collect::<Vec<_>>();
+
+    let mut sacc = SecretKey::zero();
+    let mut pacc = PublicKey::zero();
+    for (s, k) in secrets.iter().zip(keys.iter()) {
+      sacc += s;
+      pacc += k;
+    }
+
+    assert!(SecretKey::sum(&secrets) == sacc);
+    assert!(PublicKey::sum(&keys) == pacc);
+    assert!(SecretKey::sum(&secrets) * G == PublicKey::sum(&keys));
+
+    assert!(SecretKey::sum(&[]) == SecretKey::zero());
+    assert!(PublicKey::sum(&[]).is_identity());
+  }
+
+  #[test]
+  fn test_second_generator() {
+    assert!(*H != G && !H.is_identity());
+    assert!(*H == PublicKey(RistrettoPoint::hash_from_bytes::<Sha512>(&G.to_bytes())));
+  }
+
+  #[test]
+  fn test_public_key_set() {
+    let k1 = KeyPair::rand().key;
+    let k2 = KeyPair::rand().key;
+
+    let mut set = PublicKeySet::new();
+    assert!(set.insert(&k1));
+    assert!(!set.insert(&k1));
+    assert!(set.len() == 1);
+    assert!(set.contains(&k1) && !set.contains(&k2));
+
+    let set = vec![k1, k2, k1].into_iter().collect::<PublicKeySet>();
+    assert!(set.len() == 2 && set.contains(&k2));
+    assert!(set.as_bytes_set().contains(&k2.to_bytes()));
+  }
+
+  #[test]
+  fn test_fingerprint() {
+    let key = KeyPair::rand().key;
+    assert!(key.fingerprint() == key.fingerprint());
+    assert!(key.fingerprint() != KeyPair::rand().key.fingerprint());
+
+    assert!(key.fingerprint_hex().len() == 8);
+    assert!(G.fingerprint_hex() == "b4aed8a6");
+  }
+
+  #[test]
+  fn test_lambda_from_passphrase() {
+    let l1 = LambdaKey::from_passphrase("passphrase", b"salt-1");
+    let l2 = LambdaKey::from_passphrase("passphrase", b"salt-1");
+    let l3 = LambdaKey::from_passphrase("passphrase", b"salt-2");
+
+    assert!(l1 == l2);
+    assert!(l1 != l3);
+  }
+
+  #[test]
+  fn test_lambda_subkey() {
+    let lambda = LambdaKey::new(&G, b"salt");
+
+    let k1 = lambda.derive_subkey(b"field-1");
+    let k2 = lambda.derive_subkey(b"field-2");
+    assert!(k1 != k2);
+    assert!(k1 != lambda);
+    assert!(k1 == lambda.derive_subkey(b"field-1"));
+  }
+
+  #[test]
+  fn test_lambda_clear() {
+    let mut lambda = LambdaKey::new(&G, b"salt");
+    assert!(lambda.k128().is_ok());
+
+    lambda.clear();
+    assert!(lambda.is_cleared());
+    assert!(lambda.k128().is_err());
+    assert!(lambda.k512().is_err());
+  }
+
+  fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  #[test]
+  fn test_hkdf_known_answer() {
+    // RFC 5869 test case 1 inputs with SHA-512
+    let ikm = [0x0bu8; 22];
+    let salt: Vec<u8> = (0x00..=0x0c).collect();
+    let info: Vec<u8> = (0xf0..=0xf9).collect();
+
+    let okm = hkdf_sha512(&ikm, &salt, &info);
+    assert!(to_hex(&okm[..42]) == "832390086cda71fb47625bb5ceb168e4c8e26a1a16ed34d9fc7fe92c1481579338da362cb8d9f925d7cb");
+  }
+
+  #[test]
+  fn test_lambda_derive() {
+    let l1 = LambdaKey::derive(&G, b"salt", b"info");
+    assert!(to_hex(l1.k512().unwrap()) == "1547889e42d5937f8250c48448eb594f30b515b24e91bb2900f5616cf29ed90feb8164faf9baf9ce568457e6ac1c4498e195b4132e4d0b3b536d930848d2fc49");
+
+    let l2 = LambdaKey::derive(&G, b"salt", b"other");
+    assert!(l1 != l2);
+    assert!(l1 != LambdaKey::new(&G, b"salt"));
+    assert!(l1.k128().unwrap() == arrayref::array_ref!(l1.k512().unwrap(), 0, 16));
+  }
+
+  #[test]
+  fn test_lambda_ratchet() {
+    let l0 = LambdaKey::new(&G, b"salt");
+    let l1 = l0.ratchet();
+    let l2 = l1.ratchet();
+
+    assert!(l1 == l0.ratchet());
+    assert!(l0 != l1 && l1 != l2 && l0 != l2);
+    assert!(l2.ratchet() != l0 && l2.ratchet() != l1);
+    assert!(l1 != l0.derive_subkey(b"field-1"));
+  }
+}
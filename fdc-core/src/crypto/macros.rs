@@ -55,28 +55,28 @@ macro_rules! add_variants {
       impl<'a, 'b> Add<&'b $lhs> for &'a $rhs {
         type Output = $com;
         fn add(self, lhs: &'b $lhs) -> $com {
-          lhs - self
+          lhs + self
         }
       }
   
       impl Add<$lhs> for $rhs {
         type Output = $com;
         fn add(self, lhs: $lhs) -> $com {
-          &lhs - &self
+          &lhs + &self
         }
       }
   
       impl<'a> Add<&'a $lhs> for $rhs {
         type Output = $com;
         fn add(self, lhs: &'a $lhs) -> $com {
-          lhs - &self
+          lhs + &self
         }
       }
   
       impl<'a> Add<$lhs> for &'a $rhs {
         type Output = $com;
         fn add(self, lhs: $lhs) -> $com {
-          &lhs - self
+          &lhs + self
         }
       }
     )?
@@ -0,0 +1,123 @@
+use sha2::{Digest, Sha512};
+
+// domain separation between leaves and inner nodes
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+
+fn leaf(data: &[u8]) -> Vec<u8> {
+  Sha512::new().chain([LEAF]).chain(data).result().to_vec()
+}
+
+fn node(left: &[u8], right: &[u8]) -> Vec<u8> {
+  Sha512::new().chain([NODE]).chain(left).chain(right).result().to_vec()
+}
+
+// an odd last node is promoted unchanged, duplicating it would give [a, b, c] and [a, b, c, c] the same root
+fn next_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+  level.chunks(2)
+    .map(|pair| match pair {
+      [left, right] => node(left, right),
+      _ => pair[0].clone()
+    })
+    .collect()
+}
+
+pub fn merkle_root(leaves: &[Vec<u8>]) -> Option<Vec<u8>> {
+  let mut level = leaves.iter().map(|l| leaf(l)).collect::<Vec<_>>();
+  while level.len() > 1 {
+    level = next_level(&level);
+  }
+
+  level.pop()
+}
+
+pub fn merkle_proof(leaves: &[Vec<u8>], index: usize) -> Option<Vec<Vec<u8>>> {
+  if index >= leaves.len() {
+    return None
+  }
+
+  let mut proof = Vec::new();
+  let mut level = leaves.iter().map(|l| leaf(l)).collect::<Vec<_>>();
+  let mut i = index;
+  while level.len() > 1 {
+    if let Some(sibling) = level.get(i ^ 1) {
+      proof.push(sibling.clone());
+    }
+
+    level = next_level(&level);
+    i /= 2;
+  }
+
+  Some(proof)
+}
+
+// size is the number of leaves under root, it fixes where promoted nodes have no sibling
+pub fn merkle_verify(data: &[u8], proof: &[Vec<u8>], index: usize, size: usize, root: &[u8]) -> bool {
+  if index >= size {
+    return false
+  }
+
+  let mut siblings = proof.iter();
+  let mut hash = leaf(data);
+  let (mut i, mut n) = (index, size);
+  while n > 1 {
+    if i % 2 == 1 || i + 1 < n {
+      let sibling = match siblings.next() {
+        Some(sibling) => sibling,
+        None => return false
+      };
+
+      hash = match i % 2 {
+        0 => node(&hash, sibling),
+        _ => node(sibling, &hash)
+      };
+    }
+
+    i /= 2;
+    n = n.div_ceil(2);
+  }
+
+  siblings.next().is_none() && hash == root
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::rand;
+
+  #[test]
+  fn test_merkle_proofs() {
+    let leaves = (0..5).map(|_| rand(16)).collect::<Vec<_>>();
+    let root = merkle_root(&leaves).unwrap();
+
+    for (i, data) in leaves.iter().enumerate() {
+      let proof = merkle_proof(&leaves, i).unwrap();
+      assert!(merkle_verify(data, &proof, i, 5, &root));
+      assert!(!merkle_verify(data, &proof, (i + 1) % 5, 5, &root));
+    }
+
+    // the promoted last leaf doesn't verify past the end
+    let proof = merkle_proof(&leaves, 4).unwrap();
+    assert!(!merkle_verify(&leaves[4], &proof, 5, 5, &root));
+    assert!(!merkle_verify(&leaves[4], &proof, 5, 6, &root));
+
+    assert!(merkle_proof(&leaves, 5).is_none());
+    assert!(merkle_root(&[]).is_none());
+  }
+
+  #[test]
+  fn test_merkle_odd_level() {
+    let leaves = (0..3).map(|_| rand(16)).collect::<Vec<_>>();
+    let mut padded = leaves.clone();
+    padded.push(leaves[2].clone());
+
+    assert!(merkle_root(&leaves) != merkle_root(&padded));
+
+    let root = merkle_root(&leaves).unwrap();
+    let proof = merkle_proof(&leaves, 2).unwrap();
+    assert!(proof.len() == 1);
+    assert!(merkle_verify(&leaves[2], &proof, 2, 3, &root));
+    assert!(!merkle_verify(&leaves[2], &proof, 3, 3, &root));
+    assert!(!merkle_verify(&leaves[2], &proof, 3, 4, &root));
+  }
+}
@@ -4,8 +4,14 @@ mod keys;
 mod shares;
 mod signatures;
 mod encrypt;
+mod merkle;
+mod params;
+mod transcript;
 
 pub use keys::*;
 pub use shares::*;
 pub use signatures::*;
-pub use encrypt::*;
\ No newline at end of file
+pub use encrypt::*;
+pub use merkle::*;
+pub use params::*;
+pub use transcript::*;
\ No newline at end of file
@@ -0,0 +1,36 @@
+use crate::crypto::G;
+
+//-----------------------------------------------------------------------------------------------------------
+// Crypto parameters (for interop documentation)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CryptoParams {
+  pub curve: &'static str,
+  pub hash: &'static str,
+  pub signature: &'static str,
+  pub base_point: [u8; 32] // compressed ristretto encoding of G
+}
+
+pub fn params() -> CryptoParams {
+  CryptoParams {
+    curve: "ristretto255",
+    hash: "SHA-512",
+    signature: "Schnorr",
+    base_point: G.to_bytes()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+
+  #[test]
+  fn test_params() {
+    let params = params();
+    assert!(params.curve == "ristretto255");
+    assert!(params.hash == "SHA-512");
+    assert!(params.signature == "Schnorr");
+    assert!(params.base_point == RISTRETTO_BASEPOINT_COMPRESSED.to_bytes());
+  }
+}
@@ -1,9 +1,13 @@
 #![allow(non_snake_case)]
 
 use core::ops::{Add, Mul, Sub};
+use std::collections::HashSet;
 use serde::{Deserialize, Serialize};
 
-use crate::crypto::{PublicKey, SecretKey};
+use sha2::{Digest, Sha512};
+
+use crate::{error, Result};
+use crate::crypto::{PublicKey, SecretKey, KeyPair, Signature, ExtSignature, G, H};
 
 pub trait Evaluate {
   type Output;
@@ -23,56 +27,106 @@ pub struct Share {
   pub yi: SecretKey,
 }
 
+impl Share {
+  // proof of knowledge of yi such that yi * G == commit(i)
+  pub fn prove(&self, commit: &PublicPolynomial) -> SchnorrProof {
+    let kp = KeyPair { secret: self.yi.clone(), key: &self.yi * G };
+    SchnorrProof { sig: Signature::sign(&kp, &SchnorrProof::hash(commit, self.i)) }
+  }
+
+  pub fn try_add(&self, rhs: &Share) -> Result<Share> {
+    if self.i != rhs.i {
+      Err(format!("Share indices don't match ({} != {})!", self.i, rhs.i))?
+    }
+
+    Ok(self + rhs)
+  }
+
+  pub fn try_sub(&self, rhs: &Share) -> Result<Share> {
+    if self.i != rhs.i {
+      Err(format!("Share indices don't match ({} != {})!", self.i, rhs.i))?
+    }
+
+    Ok(self - rhs)
+  }
+}
+
 add_variants!(LHS = Share, RHS = Share, Output = Share);
-impl<'a, 'b> Add<&'b Share> for &'a Share {
+impl Add<&Share> for &Share {
   type Output = Share;
-  fn add(self, rhs: &'b Share) -> Share {
+  fn add(self, rhs: &Share) -> Share {
     assert!(self.i == rhs.i);
     Share { i: self.i, yi: &self.yi + &rhs.yi }
   }
 }
 
 add_variants!(LHS = Share, RHS = SecretKey, Output = Share; Commutative = Share);
-impl<'a, 'b> Add<&'b SecretKey> for &'a Share {
+impl Add<&SecretKey> for &Share {
   type Output = Share;
-  fn add(self, rhs: &'b SecretKey) -> Share {
+  fn add(self, rhs: &SecretKey) -> Share {
     Share { i: self.i, yi: &self.yi + rhs }
   }
 }
 
 sub_variants!(LHS = Share, RHS = Share, Output = Share);
-impl<'a, 'b> Sub<&'b Share> for &'a Share {
+impl Sub<&Share> for &Share {
   type Output = Share;
-  fn sub(self, rhs: &'b Share) -> Share {
+  fn sub(self, rhs: &Share) -> Share {
     assert!(self.i == rhs.i);
     Share { i: self.i, yi: &self.yi - &rhs.yi }
   }
 }
 
 sub_variants!(LHS = Share, RHS = SecretKey, Output = Share);
-impl<'a, 'b> Sub<&'b SecretKey> for &'a Share {
+impl Sub<&SecretKey> for &Share {
   type Output = Share;
-  fn sub(self, rhs: &'b SecretKey) -> Share {
+  fn sub(self, rhs: &SecretKey) -> Share {
     Share { i: self.i, yi: &self.yi - rhs }
   }
 }
 
 mul_variants!(LHS = Share, RHS = SecretKey, Output = Share; Commutative = Share);
-impl<'a, 'b> Mul<&'b SecretKey> for &'a Share {
+impl Mul<&SecretKey> for &Share {
   type Output = Share;
-  fn mul(self, rhs: &'b SecretKey) -> Share {
+  fn mul(self, rhs: &SecretKey) -> Share {
     Share { i: self.i, yi: &self.yi * rhs }
   }
 }
 
 mul_variants!(LHS = Share, RHS = PublicKey, Output = PublicShare; Commutative = PublicShare);
-impl<'a, 'b> Mul<&'b PublicKey> for &'a Share {
+impl Mul<&PublicKey> for &Share {
   type Output = PublicShare;
-  fn mul(self, rhs: &'b PublicKey) -> PublicShare {
+  fn mul(self, rhs: &PublicKey) -> PublicShare {
     PublicShare { i: self.i, Yi: &self.yi * rhs }
   }
 }
 
+//-----------------------------------------------------------------------------------------------------------
+// SchnorrProof (proof of Share knowledge)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SchnorrProof {
+  pub sig: Signature
+}
+
+impl SchnorrProof {
+  fn hash(commit: &PublicPolynomial, i: u32) -> Vec<u8> {
+    let mut hasher = Sha512::new()
+      .chain(i.to_le_bytes());
+
+    for Ak in commit.A.iter() {
+      hasher = hasher.chain(Ak.to_bytes());
+    }
+
+    hasher.result().to_vec()
+  }
+}
+
+pub fn verify_share_proof(proof: &SchnorrProof, commit: &PublicPolynomial, i: u32) -> bool {
+  let Yi = commit.evaluate(&SecretKey::from(i));
+  proof.sig.verify(&Yi, &SchnorrProof::hash(commit, i))
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // PublicShare
 //-----------------------------------------------------------------------------------------------------------
@@ -82,48 +136,82 @@ pub struct PublicShare {
   pub Yi: PublicKey,
 }
 
+impl PublicShare {
+  pub fn try_add(&self, rhs: &PublicShare) -> Result<PublicShare> {
+    if self.i != rhs.i {
+      Err(format!("Share indices don't match ({} != {})!", self.i, rhs.i))?
+    }
+
+    Ok(self + rhs)
+  }
+
+  pub fn try_sub(&self, rhs: &PublicShare) -> Result<PublicShare> {
+    if self.i != rhs.i {
+      Err(format!("Share indices don't match ({} != {})!", self.i, rhs.i))?
+    }
+
+    Ok(self - rhs)
+  }
+}
+
 add_variants!(LHS = PublicShare, RHS = PublicShare, Output = PublicShare);
-impl<'a, 'b> Add<&'b PublicShare> for &'a PublicShare {
+impl Add<&PublicShare> for &PublicShare {
   type Output = PublicShare;
-  fn add(self, rhs: &'b PublicShare) -> PublicShare {
+  fn add(self, rhs: &PublicShare) -> PublicShare {
     assert!(self.i == rhs.i);
-    PublicShare { i: self.i, Yi: &self.Yi + &rhs.Yi }
+    PublicShare { i: self.i, Yi: self.Yi + rhs.Yi }
   }
 }
 
 add_variants!(LHS = PublicShare, RHS = PublicKey, Output = PublicShare; Commutative = PublicShare);
-impl<'a, 'b> Add<&'b PublicKey> for &'a PublicShare {
+impl Add<&PublicKey> for &PublicShare {
   type Output = PublicShare;
-  fn add(self, rhs: &'b PublicKey) -> PublicShare {
+  fn add(self, rhs: &PublicKey) -> PublicShare {
     PublicShare { i: self.i, Yi: self.Yi + rhs }
   }
 }
 
 sub_variants!(LHS = PublicShare, RHS = PublicShare, Output = PublicShare);
-impl<'a, 'b> Sub<&'b PublicShare> for &'a PublicShare {
+impl Sub<&PublicShare> for &PublicShare {
   type Output = PublicShare;
-  fn sub(self, rhs: &'b PublicShare) -> PublicShare {
+  fn sub(self, rhs: &PublicShare) -> PublicShare {
     assert!(self.i == rhs.i);
-    PublicShare { i: self.i, Yi: &self.Yi - &rhs.Yi }
+    PublicShare { i: self.i, Yi: self.Yi - rhs.Yi }
   }
 }
 
 sub_variants!(LHS = PublicShare, RHS = PublicKey, Output = PublicShare);
-impl<'a, 'b> Sub<&'b PublicKey> for &'a PublicShare {
+impl Sub<&PublicKey> for &PublicShare {
   type Output = PublicShare;
-  fn sub(self, rhs: &'b PublicKey) -> PublicShare {
+  fn sub(self, rhs: &PublicKey) -> PublicShare {
     PublicShare { i: self.i, Yi: self.Yi - rhs }
   }
 }
 
 mul_variants!(LHS = PublicShare, RHS = SecretKey, Output = PublicShare; Commutative = PublicShare);
-impl<'a, 'b> Mul<&'b SecretKey> for &'a PublicShare {
+impl Mul<&SecretKey> for &PublicShare {
   type Output = PublicShare;
-  fn mul(self, rhs: &'b SecretKey) -> PublicShare {
+  fn mul(self, rhs: &SecretKey) -> PublicShare {
     PublicShare { i: self.i, Yi: self.Yi * rhs }
   }
 }
 
+// interpolation is undefined for an empty set or repeated indices
+fn check_indices<I: Iterator<Item = u32>>(indices: I) -> Result<()> {
+  let mut seen = HashSet::new();
+  for i in indices {
+    if !seen.insert(i) {
+      Err(format!("Duplicated share index {}!", i))?
+    }
+  }
+
+  if seen.is_empty() {
+    Err("No shares to recover from!")?
+  }
+
+  Ok(())
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // ShareVector
 //-----------------------------------------------------------------------------------------------------------
@@ -131,24 +219,173 @@ impl<'a, 'b> Mul<&'b SecretKey> for &'a PublicShare {
 pub struct ShareVector(pub Vec<Share>);
 
 impl ShareVector {
-  pub fn recover(&self) -> SecretKey {
+  pub fn shares_needed(&self, threshold: usize) -> usize {
+    let distinct = self.0.iter()
+      .map(|s| s.i)
+      .collect::<HashSet<_>>();
+
+    (threshold + 1).saturating_sub(distinct.len())
+  }
+
+  pub fn recover(&self) -> Result<SecretKey> {
+    Ok(self.combine(&self.weights_for_indices()?))
+  }
+
+  // Lagrange weights at x = 0 for the indices of this vector (in order), indices must be distinct
+  pub fn weights_for_indices(&self) -> Result<Vec<SecretKey>> {
+    check_indices(self.0.iter().map(|s| s.i))?;
     let range = self.0.iter()
       .map(|s| SecretKey::from(s.i))
       .collect::<Vec<_>>();
 
+    Ok((0..range.len()).map(|i| Polynomial::l_i(&range, i)).collect())
+  }
+
+  // all vectors must have the same index pattern, the weights are computed once
+  pub fn recover_many(vectors: &[ShareVector]) -> Result<Vec<SecretKey>> {
+    let first = vectors.first().ok_or_else(|| error("No share vectors to recover!"))?;
+    let weights = first.weights_for_indices()?;
+
+    let pattern = first.0.iter().map(|s| s.i).collect::<Vec<_>>();
+    for (n, vector) in vectors.iter().enumerate() {
+      if !vector.0.iter().map(|s| s.i).eq(pattern.iter().cloned()) {
+        Err(format!("Share vector {} has a different index pattern!", n))?
+      }
+    }
+
+    Ok(vectors.iter().map(|vector| vector.combine(&weights)).collect())
+  }
+
+  fn combine(&self, weights: &[SecretKey]) -> SecretKey {
     let mut acc = SecretKey::zero();
-    for (i, item) in self.0.iter().enumerate() {
-      acc += Polynomial::l_i(&range, i) * &item.yi;
+    for (wi, item) in weights.iter().zip(self.0.iter()) {
+      acc += wi * &item.yi;
     }
 
     acc
   }
+
+  pub fn recover_subset(&self, indices: &[u32]) -> Result<SecretKey> {
+    let mut subset = Vec::with_capacity(indices.len());
+    for i in indices {
+      let share = self.0.iter().find(|s| s.i == *i).ok_or_else(|| format!("No share at index {}!", i))?;
+      subset.push(share.clone());
+    }
+
+    ShareVector(subset).recover()
+  }
+
+  // DKG, sums the shares of each party index across all dealt vectors
+  pub fn aggregate(vectors: &[ShareVector]) -> Result<ShareVector> {
+    let first = vectors.first().ok_or_else(|| error("No share vectors to aggregate!"))?;
+
+    let mut acc = first.clone();
+    for vector in vectors[1..].iter() {
+      if vector.0.len() != acc.0.len() {
+        Err("Share vectors have different lengths!")?
+      }
+
+      for (share, other) in acc.0.iter_mut().zip(vector.0.iter()) {
+        *share = share.try_add(other)?;
+      }
+    }
+
+    Ok(acc)
+  }
+
+  // proactive refresh, adds shares of a zero secret so old shares can't be combined with new ones
+  pub fn refresh(&self, degree: usize) -> Result<ShareVector> {
+    check_indices(self.0.iter().map(|s| s.i))?;
+
+    let zero = Polynomial::rand(SecretKey::zero(), degree);
+    let shares = self.0.iter()
+      .map(|s| Share { i: s.i, yi: &s.yi + zero.evaluate(&SecretKey::from(s.i)) })
+      .collect();
+
+    Ok(ShareVector(shares))
+  }
+
+  // threshold is the degree of the current sharing, fewer shares would recover a wrong secret
+  pub fn redeal(&self, threshold: usize, new_t: usize, new_n: usize) -> Result<ShareVector> {
+    if self.shares_needed(threshold) > 0 {
+      Err("Not enough shares for the current threshold!")?
+    }
+
+    if new_n <= new_t {
+      Err("Not enough parties for the new threshold!")?
+    }
+
+    let secret = self.recover()?;
+    Ok(Polynomial::rand(secret, new_t).shares(new_n))
+  }
+
+  // every current share is dealt to the new committee, the secret is never reconstructed in one place
+  // threshold is the degree of the current sharing, like in redeal
+  pub fn reshare(&self, threshold: usize, new_parties: usize, new_threshold: usize) -> Result<Vec<DealtShares>> {
+    if self.shares_needed(threshold) > 0 {
+      Err("Not enough shares for the current threshold!")?
+    }
+
+    if new_parties <= new_threshold {
+      Err("Not enough parties for the new threshold!")?
+    }
+
+    check_indices(self.0.iter().map(|s| s.i))?;
+    let dealt = self.0.iter()
+      .map(|s| {
+        let poly = Polynomial::rand(s.yi.clone(), new_threshold);
+        DealtShares { dealer: s.i, commitment: poly.commitment(), shares: poly.shares(new_parties) }
+      })
+      .collect();
+
+    Ok(dealt)
+  }
+
+  // the new share j is the Lagrange combination (over the dealer indices) of the sub-shares received at j
+  // at most threshold dealers would combine to shares of a wrong secret
+  pub fn combine_reshared(dealt: &[DealtShares], threshold: usize) -> Result<ShareVector> {
+    check_indices(dealt.iter().map(|d| d.dealer))?;
+    if dealt.len() <= threshold {
+      Err("Not enough dealers for the current threshold!")?
+    }
+
+    let range = dealt.iter()
+      .map(|d| SecretKey::from(d.dealer))
+      .collect::<Vec<_>>();
+
+    let first = &dealt[0].shares;
+    let mut acc = first * &Polynomial::l_i(&range, 0);
+    for (n, item) in dealt.iter().enumerate().skip(1) {
+      if item.shares.0.len() != acc.0.len() {
+        Err("Dealt share vectors have different lengths!")?
+      }
+
+      let weighted = &item.shares * &Polynomial::l_i(&range, n);
+      for (share, other) in acc.0.iter_mut().zip(weighted.0.iter()) {
+        *share = share.try_add(other)?;
+      }
+    }
+
+    Ok(acc)
+  }
+
+  // Feldman VSS, every share must lie on the committed polynomial
+  pub fn verify_against(&self, commit: &PublicPolynomial) -> Result<()> {
+    commit.verify_subset(&(self * G).0)
+  }
+}
+
+impl Mul<&SecretKey> for &ShareVector {
+  type Output = ShareVector;
+  fn mul(self, rhs: &SecretKey) -> ShareVector {
+    ShareVector(self.0.iter().map(|s| s * rhs).collect())
+  }
 }
 
 mul_variants!(LHS = ShareVector, RHS = PublicKey, Output = PublicShareVector; Commutative = PublicShareVector);
-impl<'a, 'b> Mul<&'b PublicKey> for &'a ShareVector {
+impl Mul<&PublicKey> for &ShareVector {
   type Output = PublicShareVector;
-  fn mul(self, rhs: &'b PublicKey) -> PublicShareVector {
+  fn mul(self, rhs: &PublicKey) -> PublicShareVector {
     let res: Vec<PublicShare> = self.0.iter().map(|s| s * rhs).collect();
     PublicShareVector(res)
   }
@@ -161,7 +398,16 @@ impl<'a, 'b> Mul<&'b PublicKey> for &'a ShareVector {
 pub struct PublicShareVector(pub Vec<PublicShare>);
 
 impl PublicShareVector {
-  pub fn recover(&self) -> PublicKey {
+  pub fn to_pairs(&self) -> Vec<(u32, PublicKey)> {
+    self.0.iter().map(|s| (s.i, s.Yi)).collect()
+  }
+
+  pub fn from_pairs(pairs: &[(u32, PublicKey)]) -> PublicShareVector {
+    PublicShareVector(pairs.iter().map(|(i, Yi)| PublicShare { i: *i, Yi: *Yi }).collect())
+  }
+
+  pub fn recover(&self) -> Result<PublicKey> {
+    check_indices(self.0.iter().map(|s| s.i))?;
     let range = self.0.iter()
       .map(|s| SecretKey::from(s.i))
       .collect::<Vec<_>>();
@@ -171,7 +417,7 @@ impl PublicShareVector {
       acc += Polynomial::l_i(&range, i) * item.Yi;
     }
 
-    acc
+    Ok(acc)
   }
 }
 
@@ -184,17 +430,17 @@ pub struct Polynomial {
 }
 
 mul_variants!(LHS = Polynomial, RHS = SecretKey, Output = Polynomial; Commutative = Polynomial);
-impl<'a, 'b> Mul<&'b SecretKey> for &'a Polynomial {
+impl Mul<&SecretKey> for &Polynomial {
   type Output = Polynomial;
-  fn mul(self, rhs: &'b SecretKey) -> Polynomial {
+  fn mul(self, rhs: &SecretKey) -> Polynomial {
     Polynomial { a: self.a.iter().map(|ak| ak * rhs).collect::<Vec<SecretKey>>() }
   }
 }
 
 mul_variants!(LHS = Polynomial, RHS = PublicKey, Output = PublicPolynomial; Commutative = PublicPolynomial);
-impl<'a, 'b> Mul<&'b PublicKey> for &'a Polynomial {
+impl Mul<&PublicKey> for &Polynomial {
   type Output = PublicPolynomial;
-  fn mul(self, rhs: &'b PublicKey) -> PublicPolynomial {
+  fn mul(self, rhs: &PublicKey) -> PublicPolynomial {
     PublicPolynomial { A: self.a.iter().map(|ak| ak * rhs).collect::<Vec<_>>() }
   }
 }
@@ -202,12 +448,51 @@ impl<'a, 'b> Mul<&'b PublicKey> for &'a Polynomial {
 impl Polynomial {
   pub fn rand(secret: SecretKey, degree: usize) -> Self {
     let mut coefs = vec![secret];
-    let rnd_coefs: Vec<SecretKey> = (0..degree).map(|_| SecretKey::rand()).collect();
+    let rnd_coefs: Vec<SecretKey> = (0..degree).map(|_| SecretKey::rand_nonzero()).collect();
     coefs.extend(rnd_coefs);
 
     Polynomial { a: coefs }
   }
 
+  pub fn group_key(&self) -> PublicKey {
+    &self.a[0] * G
+  }
+
+  pub fn at(&self, x: &SecretKey) -> SecretKey {
+    self.evaluate(x)
+  }
+
+  pub fn reconstruct_from(shares: &[Share]) -> Result<SecretKey> {
+    ShareVector(shares.to_vec()).recover()
+  }
+
+  pub fn commitment(&self) -> PublicPolynomial {
+    self * G
+  }
+
+  // hiding commitment a_k*G + b_k*H, the blinding polynomial must have the same degree
+  pub fn pedersen_commit(&self, blinding: &Polynomial) -> Result<PublicPolynomial> {
+    self.commit_with(blinding, &H)
+  }
+
+  fn commit_with(&self, blinding: &Polynomial, h: &PublicKey) -> Result<PublicPolynomial> {
+    if self.a.len() != blinding.a.len() {
+      Err("Blinding polynomial has a different degree!")?
+    }
+
+    let A = self.a.iter().zip(blinding.a.iter())
+      .map(|(ak, bk)| ak * G + bk * h)
+      .collect();
+
+    Ok(PublicPolynomial { A })
+  }
+
+  pub fn vss_bundle(&self, dealer: &KeyPair, n: usize) -> VssBundle {
+    let commitment = self.commitment();
+    let dealer_sig = ExtSignature::sign(dealer, &VssBundle::hash(&commitment));
+    VssBundle { commitment, dealer_sig, shares: self.shares(n) }
+  }
+
   pub fn shares(&self, n: usize) -> ShareVector {
     let mut shares = Vec::<Share>::with_capacity(n);
     for j in 1..=n {
@@ -250,6 +535,56 @@ impl Degree for Polynomial {
   }
 }
 
+//-----------------------------------------------------------------------------------------------------------
+// VssBundle (shares with the dealer signed commitment)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VssBundle {
+  pub commitment: PublicPolynomial,
+  pub dealer_sig: ExtSignature,
+  pub shares: ShareVector
+}
+
+impl VssBundle {
+  pub fn verify(&self) -> Result<()> {
+    if !self.dealer_sig.verify(&VssBundle::hash(&self.commitment)) {
+      Err("Invalid dealer signature!")?
+    }
+
+    self.shares.verify_against(&self.commitment)
+  }
+
+  fn hash(commit: &PublicPolynomial) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    for Ak in commit.A.iter() {
+      hasher = hasher.chain(Ak.to_bytes());
+    }
+
+    hasher.result().to_vec()
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// DealtShares (sub-shares of one current share for the new committee)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DealtShares {
+  pub dealer: u32,
+  pub commitment: PublicPolynomial,
+  pub shares: ShareVector
+}
+
+impl DealtShares {
+  // the commitment must open to the dealer public share, Yi = A0
+  pub fn verify(&self, dealer_share: &PublicShare) -> Result<()> {
+    if dealer_share.i != self.dealer || !self.commitment.commits_to(&dealer_share.Yi) {
+      Err("Dealt commitment doesn't match the dealer share!")?
+    }
+
+    self.shares.verify_against(&self.commitment)
+  }
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // PublicPolynomial
 //-----------------------------------------------------------------------------------------------------------
@@ -259,18 +594,61 @@ pub struct PublicPolynomial {
 }
 
 mul_variants!(LHS = PublicPolynomial, RHS = SecretKey, Output = PublicPolynomial; Commutative = PublicPolynomial);
-impl<'a, 'b> Mul<&'b SecretKey> for &'a PublicPolynomial {
+impl Mul<&SecretKey> for &PublicPolynomial {
   type Output = PublicPolynomial;
-  fn mul(self, rhs: &'b SecretKey) -> PublicPolynomial {
+  fn mul(self, rhs: &SecretKey) -> PublicPolynomial {
     PublicPolynomial { A: self.A.iter().map(|Ak| Ak * rhs).collect::<Vec<_>>() }
   }
 }
 
 impl PublicPolynomial {
+  // DKG, coefficient-wise sum of the dealt commitments
+  pub fn aggregate(commits: &[PublicPolynomial]) -> Result<PublicPolynomial> {
+    let first = commits.first().ok_or_else(|| error("No commitments to aggregate!"))?;
+
+    let mut acc = first.clone();
+    for commit in commits[1..].iter() {
+      if commit.A.len() != acc.A.len() {
+        Err("Commitments have different degrees!")?
+      }
+
+      for (Ak, Bk) in acc.A.iter_mut().zip(commit.A.iter()) {
+        *Ak += Bk;
+      }
+    }
+
+    Ok(acc)
+  }
+
+  pub fn at(&self, x: &SecretKey) -> PublicKey {
+    self.evaluate(x)
+  }
+
+  // share and blinding share of the same index must open the Pedersen commitment
+  pub fn verify_pedersen(&self, share: &Share, blinding: &Share) -> bool {
+    let x = SecretKey::from(u64::from(share.i));
+    share.i == blinding.i && &share.yi * G + &blinding.yi * *H == self.evaluate(&x)
+  }
+
   pub fn verify(&self, share: &PublicShare) -> bool {
     let x = SecretKey::from(u64::from(share.i));
     share.Yi == self.evaluate(&x)
   }
+
+  pub fn commits_to(&self, group_key: &PublicKey) -> bool {
+    self.A.first() == Some(group_key)
+  }
+
+  pub fn verify_pair(&self, a: &PublicShare, b: &PublicShare) -> bool {
+    self.verify(a) && self.verify(b)
+  }
+
+  pub fn verify_subset(&self, shares: &[PublicShare]) -> Result<()> {
+    match shares.iter().find(|s| !self.verify(s)) {
+      Some(share) => Err(format!("Invalid share at index {}!", share.i))?,
+      None => Ok(())
+    }
+  }
 }
 
 impl Evaluate for PublicPolynomial {
@@ -293,7 +671,7 @@ impl Degree for PublicPolynomial {
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::crypto::G;
+  use crate::crypto::KeyPair;
 
   #[test]
   fn test_reconstruct() {
@@ -308,10 +686,333 @@ mod tests {
     let shares = poly.shares(parties);
     let S_shares = &shares * G;
 
-    let r_s = shares.recover();
+    let r_s = shares.recover().unwrap();
     assert!(s == r_s);
 
-    let r_S = S_shares.recover();
+    let r_S = S_shares.recover().unwrap();
     assert!(S == r_S);
   }
+
+  #[test]
+  fn test_recover_subset() {
+    let s = SecretKey::rand();
+    let shares = Polynomial::rand(s.clone(), 2).shares(6);
+
+    for subset in [[1, 2, 3], [4, 5, 6], [6, 1, 4], [2, 5, 3]].iter() {
+      assert!(shares.recover_subset(subset).unwrap() == s);
+    }
+
+    let picked = vec![shares.0[5].clone(), shares.0[0].clone(), shares.0[2].clone()];
+    assert!(Polynomial::reconstruct_from(&picked).unwrap() == s);
+
+    assert!(shares.recover_subset(&[1, 2, 7]).err().unwrap().to_string() == "No share at index 7!");
+    assert!(shares.recover_subset(&[1, 2]).unwrap() != s);
+  }
+
+  #[test]
+  fn test_pedersen_commit() {
+    let poly = Polynomial::rand(SecretKey::rand(), 2);
+    let blinding = Polynomial::rand(SecretKey::rand(), 2);
+    let commit = poly.pedersen_commit(&blinding).unwrap();
+    assert!(!commit.commits_to(&poly.group_key()));
+    assert!(poly.pedersen_commit(&Polynomial::rand(SecretKey::rand(), 3)).is_err());
+
+    let shares = poly.shares(4);
+    let bshares = blinding.shares(4);
+    assert!(commit.verify_pedersen(&shares.0[1], &bshares.0[1]));
+    assert!(!commit.verify_pedersen(&shares.0[1], &bshares.0[2]));
+    assert!(!commit.verify_pedersen(&shares.0[1], &Share { i: 2, yi: SecretKey::rand() }));
+
+    // with a known log_G(h) any other secret opens the same commitment, H must not have one
+    let x = SecretKey::rand();
+    let h = &x * G;
+    let other = Polynomial::rand(SecretKey::rand(), 2);
+    let other_blinding = Polynomial {
+      a: (0..3).map(|k| &blinding.a[k] + (&poly.a[k] - &other.a[k]) * x.invert()).collect()
+    };
+
+    assert!(other.a[0] != poly.a[0]);
+    assert!(poly.commit_with(&blinding, &h).unwrap() == other.commit_with(&other_blinding, &h).unwrap());
+  }
+
+  #[test]
+  fn test_evaluate_at() {
+    let poly = Polynomial::rand(SecretKey::rand(), 3);
+    let commit = poly.commitment();
+
+    let x = SecretKey::rand();
+    assert!(poly.at(&x) * G == commit.at(&x));
+    assert!(poly.at(&SecretKey::zero()) == poly.a[0]);
+  }
+
+  #[test]
+  fn test_dkg_aggregate() {
+    let secrets = (0..3).map(|_| SecretKey::rand()).collect::<Vec<_>>();
+    let polys = secrets.iter().map(|s| Polynomial::rand(s.clone(), 2)).collect::<Vec<_>>();
+
+    let vectors = polys.iter().map(|p| p.shares(5)).collect::<Vec<_>>();
+    let commits = polys.iter().map(|p| p.commitment()).collect::<Vec<_>>();
+
+    let shares = ShareVector::aggregate(&vectors).unwrap();
+    let commit = PublicPolynomial::aggregate(&commits).unwrap();
+
+    let joint = &(&secrets[0] + &secrets[1]) + &secrets[2];
+    assert!(shares.recover_subset(&[1, 3, 5]).unwrap() == joint);
+    assert!(commit.commits_to(&(&joint * G)));
+    assert!(shares.verify_against(&commit).is_ok());
+
+    let short = ShareVector(vectors[1].0[..4].to_vec());
+    assert!(ShareVector::aggregate(&[vectors[0].clone(), short]).is_err());
+
+    let mut shifted = vectors[1].clone();
+    shifted.0[2].i = 9;
+    assert!(ShareVector::aggregate(&[vectors[0].clone(), shifted]).is_err());
+
+    assert!(PublicPolynomial::aggregate(&[commits[0].clone(), Polynomial::rand(SecretKey::rand(), 3).commitment()]).is_err());
+    assert!(ShareVector::aggregate(&[]).is_err());
+  }
+
+  #[test]
+  fn test_refresh() {
+    let original = Polynomial::rand(SecretKey::rand(), 2).shares(5);
+    let refreshed = original.refresh(2).unwrap();
+
+    assert!(refreshed.recover().unwrap() == original.recover().unwrap());
+    assert!(refreshed.recover_subset(&[1, 4, 5]).unwrap() == original.recover().unwrap());
+    assert!(refreshed.0 != original.0);
+
+    // mixing old and refreshed shares doesn't recover the secret
+    let mixed = ShareVector(vec![original.0[0].clone(), refreshed.0[1].clone(), refreshed.0[2].clone()]);
+    assert!(mixed.recover().unwrap() != original.recover().unwrap());
+
+    assert!(ShareVector(vec![]).refresh(2).is_err());
+  }
+
+  #[test]
+  fn test_redeal() {
+    let s = SecretKey::rand();
+    let shares = Polynomial::rand(s.clone(), 2).shares(4);
+
+    let redealt = shares.redeal(2, 4, 7).unwrap();
+    assert!(redealt.0.len() == 7);
+    assert!(redealt.recover_subset(&[2, 3, 5, 6, 7]).unwrap() == s);
+    assert!(redealt.recover_subset(&[2, 3, 5, 6]).unwrap() != s);
+
+    assert!(shares.redeal(2, 3, 3).is_err());
+    assert!(ShareVector(vec![]).redeal(0, 1, 3).is_err());
+
+    // below the current threshold the redeal is refused instead of sharing a wrong secret
+    let partial = ShareVector(shares.0[..2].to_vec());
+    let err = partial.redeal(2, 4, 7).err().unwrap();
+    assert!(err.to_string() == "Not enough shares for the current threshold!");
+    assert!(ShareVector(shares.0[..3].to_vec()).redeal(2, 4, 7).unwrap().recover().unwrap() == s);
+  }
+
+  #[test]
+  fn test_reshare() {
+    let s = SecretKey::rand();
+    let poly = Polynomial::rand(s.clone(), 2);
+    let shares = poly.shares(5);
+    let commit = poly.commitment();
+
+    // 3-of-5 to 4-of-9, with shareholders 2, 4 and 5
+    let current = ShareVector(vec![shares.0[1].clone(), shares.0[3].clone(), shares.0[4].clone()]);
+    let dealt = current.reshare(2, 9, 3).unwrap();
+    assert!(dealt.len() == 3);
+    for (d, share) in dealt.iter().zip(current.0.iter()) {
+      assert!(d.verify(&(share * G)).is_ok());
+      assert!(commit.verify(&(share * G)));
+    }
+    assert!(dealt[0].verify(&(&current.0[1] * G)).is_err());
+
+    let reshared = ShareVector::combine_reshared(&dealt, 2).unwrap();
+    assert!(reshared.0.len() == 9);
+    assert!(reshared.recover_subset(&[1, 4, 6, 9]).unwrap() == s);
+    assert!(reshared.recover_subset(&[1, 4, 6]).unwrap() != s);
+    assert!(reshared.recover_subset(&[2, 3, 5, 7, 8]).unwrap() == s);
+
+    assert!(current.reshare(2, 3, 3).is_err());
+    assert!(ShareVector::combine_reshared(&[], 2).is_err());
+
+    // two dealers of a degree 2 sharing combine to shares of a wrong secret
+    assert!(ShareVector(current.0[..2].to_vec()).reshare(2, 9, 3).is_err());
+    assert!(ShareVector::combine_reshared(&dealt[..2], 2).is_err());
+    assert!(ShareVector::combine_reshared(&dealt[..2], 1).unwrap().recover_subset(&[1, 4, 6, 9]).unwrap() != s);
+  }
+
+  #[test]
+  fn test_recover_many() {
+    let secrets = (0..3).map(|_| SecretKey::rand()).collect::<Vec<_>>();
+    let vectors = secrets.iter()
+      .map(|s| {
+        let shares = Polynomial::rand(s.clone(), 2).shares(5);
+        ShareVector(vec![shares.0[4].clone(), shares.0[1].clone(), shares.0[2].clone()])
+      })
+      .collect::<Vec<_>>();
+
+    let recovered = ShareVector::recover_many(&vectors).unwrap();
+    assert!(recovered == secrets);
+    for (vector, secret) in vectors.iter().zip(recovered.iter()) {
+      assert!(vector.recover().unwrap() == *secret);
+    }
+
+    let weights = vectors[0].weights_for_indices().unwrap();
+    assert!(weights.len() == 3 && weights == vectors[2].weights_for_indices().unwrap());
+
+    let mut duplicated = vectors[0].clone();
+    duplicated.0[1].i = duplicated.0[0].i;
+    assert!(duplicated.weights_for_indices().is_err());
+
+    let mut reordered = vectors.clone();
+    reordered[1].0.swap(0, 1);
+    let err = ShareVector::recover_many(&reordered).err().unwrap();
+    assert!(err.to_string() == "Share vector 1 has a different index pattern!");
+    assert!(ShareVector::recover_many(&[]).is_err());
+  }
+
+  #[test]
+  fn test_recover_invalid() {
+    let shares = Polynomial::rand(SecretKey::rand(), 2).shares(4);
+
+    let duplicated = ShareVector(vec![shares.0[0].clone(), shares.0[1].clone(), shares.0[1].clone()]);
+    assert!(duplicated.recover().err().unwrap().to_string() == "Duplicated share index 2!");
+    assert!((&duplicated * G).recover().is_err());
+
+    assert!(ShareVector(vec![]).recover().is_err());
+    assert!(PublicShareVector(vec![]).recover().is_err());
+  }
+
+  #[test]
+  fn test_shares_needed() {
+    let poly = Polynomial::rand(SecretKey::rand(), 3);
+    let shares = poly.shares(5);
+
+    let partial = ShareVector(vec![shares.0[0].clone(), shares.0[1].clone(), shares.0[1].clone()]);
+    assert!(partial.shares_needed(3) == 2);
+    assert!(shares.shares_needed(3) == 0);
+  }
+
+  #[test]
+  fn test_verify_pair() {
+    let poly = Polynomial::rand(SecretKey::rand(), 2);
+    let other = Polynomial::rand(SecretKey::rand(), 2);
+    let commit = &poly * G;
+
+    let shares = &poly.shares(4) * G;
+    let other_shares = &other.shares(4) * G;
+
+    assert!(commit.verify_pair(&shares.0[0], &shares.0[1]));
+    assert!(!commit.verify_pair(&shares.0[0], &other_shares.0[1]));
+
+    assert!(commit.verify_subset(&shares.0).is_ok());
+
+    let mut mixed = shares.0.clone();
+    mixed[2] = other_shares.0[2].clone();
+    let err = commit.verify_subset(&mixed).err().unwrap();
+    assert!(err.to_string() == "Invalid share at index 3!");
+  }
+
+  #[test]
+  fn test_verify_against() {
+    let poly = Polynomial::rand(SecretKey::rand(), 2);
+    let commit = poly.commitment();
+    assert!(commit == &poly * G);
+
+    let mut shares = poly.shares(5);
+    assert!(shares.verify_against(&commit).is_ok());
+
+    shares.0[3].yi = SecretKey::rand();
+    let err = shares.verify_against(&commit).err().unwrap();
+    assert!(err.to_string() == "Invalid share at index 4!");
+  }
+
+  #[test]
+  fn test_vss_bundle() {
+    let dealer = KeyPair::rand();
+    let poly = Polynomial::rand(SecretKey::rand(), 2);
+
+    let mut bundle = poly.vss_bundle(&dealer, 5);
+    assert!(bundle.verify().is_ok());
+    assert!(bundle.dealer_sig.key == dealer.key);
+
+    bundle.shares.0[1].yi = SecretKey::rand();
+    let err = bundle.verify().err().unwrap();
+    assert!(err.to_string() == "Invalid share at index 2!");
+
+    let mut forged = poly.vss_bundle(&dealer, 5);
+    forged.commitment = Polynomial::rand(SecretKey::rand(), 2).commitment();
+    assert!(forged.verify().is_err());
+  }
+
+  #[test]
+  fn test_public_share_pairs() {
+    let poly = Polynomial::rand(SecretKey::rand(), 2);
+    let shares = &poly.shares(4) * G;
+
+    let pairs = shares.to_pairs();
+    assert!(PublicShareVector::from_pairs(&pairs) == shares);
+
+    let loaded = PublicShareVector::from_pairs(&[(7, G), (3, G)]);
+    assert!(loaded.0.iter().map(|s| s.i).collect::<Vec<_>>() == vec![7, 3]);
+  }
+
+  #[test]
+  fn test_commits_to() {
+    let poly = Polynomial::rand(SecretKey::rand(), 3);
+    let commit = &poly * G;
+
+    assert!(commit.commits_to(&poly.group_key()));
+    assert!(!commit.commits_to(&KeyPair::rand().key));
+  }
+
+  #[test]
+  fn test_share_proof() {
+    let poly = Polynomial::rand(SecretKey::rand(), 2);
+    let commit = &poly * G;
+    let shares = poly.shares(4);
+
+    let proof = shares.0[1].prove(&commit);
+    assert!(verify_share_proof(&proof, &commit, shares.0[1].i));
+    assert!(!verify_share_proof(&proof, &commit, shares.0[2].i));
+
+    let other = &Polynomial::rand(SecretKey::rand(), 2) * G;
+    assert!(!verify_share_proof(&proof, &other, shares.0[1].i));
+  }
+
+  #[test]
+  fn test_commutative_add() {
+    let k = SecretKey::rand();
+    let share = Share { i: 3, yi: SecretKey::rand() };
+    let sum = Share { i: 3, yi: &share.yi + &k };
+
+    assert!(&share + &k == sum);
+    assert!(&k + &share == sum);
+    assert!(k.clone() + share.clone() == share.clone() + k.clone());
+
+    let K = &k * G;
+    let pshare = PublicShare { i: 5, Yi: &SecretKey::rand() * G };
+    let psum = PublicShare { i: 5, Yi: pshare.Yi + K };
+
+    assert!(&pshare + K == psum);
+    assert!(K + &pshare == psum);
+    assert!(K + pshare.clone() == pshare + K);
+  }
+
+  #[test]
+  fn test_try_add_sub() {
+    let a = Share { i: 2, yi: SecretKey::rand() };
+    let b = Share { i: 2, yi: SecretKey::rand() };
+    let c = Share { i: 3, yi: SecretKey::rand() };
+
+    assert!(a.try_add(&b).unwrap() == &a + &b);
+    assert!(a.try_sub(&b).unwrap() == &a - &b);
+    assert!(a.try_add(&c).is_err());
+    assert!(a.try_sub(&c).err().unwrap().to_string() == "Share indices don't match (2 != 3)!");
+
+    let (A, B, C) = (&a * G, &b * G, &c * G);
+    assert!(A.try_add(&B).unwrap() == &A + &B);
+    assert!(A.try_sub(&B).unwrap() == &A - &B);
+    assert!(A.try_add(&C).is_err());
+    assert!(A.try_sub(&C).is_err());
+  }
 }
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 use crate::crypto::{KeyPair, PublicKey, SecretKey, G};
 use sha2::{Digest, Sha512};
@@ -10,11 +11,15 @@ use sha2::{Digest, Sha512};
 //-----------------------------------------------------------------------------------------------------------
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Signature {
-  pub c: SecretKey,
+  pub M: PublicKey,
   pub p: SecretKey,
 }
 
+// secret nonce of the interactive protocol, it can't be copied and respond() consumes it
+pub struct Nonce(SecretKey, PublicKey);
+
 impl Signature {
+  // the deterministic nonce is safe here because the challenge is always H(key, M, dhash)
   pub fn sign(kp: &KeyPair, dhash: &[u8]) -> Self {
     let hasher = Sha512::new()
       .chain(kp.secret.as_bytes())
@@ -23,27 +28,51 @@ impl Signature {
     let m = SecretKey::from_hash(hasher);
     let M = &m * G;
 
-    let hasher = Sha512::new()
-      .chain(kp.key.to_bytes())
-      .chain(M.to_bytes())
-      .chain(dhash);
+    let c = Signature::challenge(&kp.key, &M, dhash);
+    Signature::response(&m, M, &c, &kp.secret)
+  }
 
-    let c = SecretKey::from_hash(hasher);
-    let p = m - &c * &kp.secret;
+  // first message of the interactive protocol, a fresh random nonce and its commitment
+  // a nonce answering two challenges leaks the secret key, so it is single-use
+  pub fn commit() -> (Nonce, PublicKey) {
+    let m = SecretKey::rand_nonzero();
+    let M = &m * G;
+
+    (Nonce(m, M), M)
+  }
+
+  pub fn respond(nonce: Nonce, challenge: &SecretKey, secret: &SecretKey) -> Self {
+    Signature::response(&nonce.0, nonce.1, challenge, secret)
+  }
+
+  fn response(nonce: &SecretKey, M: PublicKey, challenge: &SecretKey, secret: &SecretKey) -> Self {
+    let p = nonce - challenge * secret;
+    Self { M, p }
+  }
 
-    Self { c, p }
+  // the verification equation as a point, c*Y + p*G with c = H(Y, M, dhash), it equals M only for a valid signature
+  pub fn recover_commitment(&self, key: &PublicKey, dhash: &[u8]) -> PublicKey {
+    Signature::challenge(key, &self.M, dhash) * key + &self.p * G
   }
 
+  // with the identity as key any p opens to M = p*G, so (M, p) would verify for every dhash
   pub fn verify(&self, key: &PublicKey, dhash: &[u8]) -> bool {
-    let M = &self.c * key + &self.p * G;
+    !key.is_identity() && self.recover_commitment(key, dhash) == self.M
+  }
+
+  // interactive verification, the verifier chose the challenge after receiving the commitment M
+  // checks p*G + challenge*Y == M instead of recomputing c from dhash
+  pub fn verify_with_challenge(&self, key: &PublicKey, commit: &PublicKey, challenge: &SecretKey) -> bool {
+    !key.is_identity() && self.M == *commit && challenge * key + &self.p * G == *commit
+  }
 
+  pub fn challenge(key: &PublicKey, M: &PublicKey, dhash: &[u8]) -> SecretKey {
     let hasher = Sha512::new()
       .chain(key.to_bytes())
       .chain(M.to_bytes())
       .chain(dhash);
 
-    let c = SecretKey::from_hash(hasher);
-    c == self.c
+    SecretKey::from_hash(hasher)
   }
 }
 
@@ -65,14 +94,146 @@ impl ExtSignature {
   pub fn verify(&self, dhash: &[u8]) -> bool {
     self.sig.verify(&self.key, dhash)
   }
+
+  pub fn verify_unrevoked(&self, dhash: &[u8], revoked: &HashSet<[u8; 32]>) -> bool {
+    !revoked.contains(&self.key.to_bytes()) && self.verify(dhash)
+  }
+
+  // random linear combination of every p_i*G + c_i*Y_i - M_i, checked with a single multi-scalar multiplication
+  // true only if every signature is valid, a false result doesn't pinpoint which one failed
+  pub fn verify_batch(items: &[(ExtSignature, Vec<u8>)]) -> bool {
+    if items.iter().any(|(sig, _)| sig.key.is_identity()) {
+      return false
+    }
+
+    let mut scalars = Vec::with_capacity(2 * items.len() + 1);
+    let mut points = Vec::with_capacity(2 * items.len() + 1);
+
+    let mut pG = SecretKey::zero();
+    for (sig, dhash) in items.iter() {
+      let z = SecretKey::rand_nonzero();
+      let c = Signature::challenge(&sig.key, &sig.sig.M, dhash);
+
+      pG += &(&z * &sig.sig.p);
+      scalars.push(&z * &c);
+      points.push(sig.key);
+      scalars.push(-&z);
+      points.push(sig.sig.M);
+    }
+
+    scalars.push(pG);
+    points.push(G);
+
+    PublicKey::vartime_multiscalar_mul(&scalars, &points).is_identity()
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// MuSig2 (multi-party Schnorr signature verified with Signature::verify against the aggregate key)
+// Two nonces per signer bound by b = H(X, R1, R2, dhash) keep concurrent sessions safe (Nick, Ruffing, Seurin).
+//-----------------------------------------------------------------------------------------------------------
+pub mod musig {
+  use super::*;
+  use crate::{error, Result};
+
+  // round 1 output, a nonce is single-use: it can't be cloned and partial_sign consumes it
+  pub struct MuSigNonce {
+    r1: SecretKey,
+    r2: SecretKey,
+    pub commit: MuSigCommit
+  }
+
+  #[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+  pub struct MuSigCommit {
+    pub r1: PublicKey,
+    pub r2: PublicKey
+  }
+
+  impl MuSigNonce {
+    pub fn rand() -> Self {
+      let r1 = SecretKey::rand_nonzero();
+      let r2 = SecretKey::rand_nonzero();
+      let commit = MuSigCommit { r1: &r1 * G, r2: &r2 * G };
+      Self { r1, r2, commit }
+    }
+  }
+
+  // b = H(X, R1, R2, dhash), the effective nonce R1 + b*R2 depends on every commit of the session
+  fn nonce_coefficient(agg: &PublicKey, R1: &PublicKey, R2: &PublicKey, dhash: &[u8]) -> SecretKey {
+    let hasher = Sha512::new()
+      .chain(agg.to_bytes())
+      .chain(R1.to_bytes())
+      .chain(R2.to_bytes())
+      .chain(dhash);
+
+    SecretKey::from_hash(hasher)
+  }
+
+  // a_i = H(L, X_i), binds every key to the full signer set against rogue-key attacks
+  fn coefficient(keys: &[PublicKey], key: &PublicKey) -> SecretKey {
+    let mut hasher = Sha512::new();
+    for k in keys.iter() {
+      hasher = hasher.chain(k.to_bytes());
+    }
+
+    SecretKey::from_hash(hasher.chain(key.to_bytes()))
+  }
+
+  pub fn aggregate_keys(keys: &[PublicKey]) -> PublicKey {
+    let mut acc = PublicKey::zero();
+    for key in keys.iter() {
+      acc += coefficient(keys, key) * key;
+    }
+
+    acc
+  }
+
+  // round 2, every signer uses the same keys and commits (in the same order)
+  pub fn partial_sign(kp: &KeyPair, keys: &[PublicKey], nonce: MuSigNonce, commits: &[MuSigCommit], dhash: &[u8]) -> Result<Signature> {
+    if !keys.contains(&kp.key) {
+      Err("Signer is not in the key set!")?
+    }
+
+    if !commits.contains(&nonce.commit) {
+      Err("Signer commit is not in the commit set!")?
+    }
+
+    let agg = aggregate_keys(keys);
+    let R1 = commits.iter().fold(PublicKey::zero(), |acc, c| acc + c.r1);
+    let R2 = commits.iter().fold(PublicKey::zero(), |acc, c| acc + c.r2);
+
+    let b = nonce_coefficient(&agg, &R1, &R2, dhash);
+    let R = R1 + &b * R2;
+
+    let c = Signature::challenge(&agg, &R, dhash);
+    let secret = coefficient(keys, &kp.key) * &kp.secret;
+    Ok(Signature::response(&(&nonce.r1 + &b * &nonce.r2), R, &c, &secret))
+  }
+
+  pub fn aggregate_signatures(partials: &[Signature]) -> Result<Signature> {
+    let first = partials.first().ok_or_else(|| error("No partial signatures to aggregate!"))?;
+
+    let mut p = SecretKey::zero();
+    for partial in partials.iter() {
+      if partial.M != first.M {
+        Err("Partial signatures have different commitments!")?
+      }
+
+      p += &partial.p;
+    }
+
+    Ok(Signature { M: first.M, p })
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use super::musig::*;
   use crate::rand;
 
   #[test]
+  #[allow(clippy::bool_comparison)]
   fn test_correct() {
     let kpa = KeyPair::rand();
 
@@ -89,6 +250,7 @@ mod tests {
   }
 
   #[test]
+  #[allow(clippy::bool_comparison)]
   fn test_incorrect() {
     let kpa = KeyPair::rand();
 
@@ -110,4 +272,169 @@ mod tests {
 
     assert!(sig.verify(dhash2.as_slice()) == false);
   }
+
+  #[test]
+  fn test_revoked() {
+    let kpa = KeyPair::rand();
+    let kpb = KeyPair::rand();
+
+    let dhash = Sha512::new()
+      .chain(rand(10))
+      .result();
+
+    let mut revoked = HashSet::new();
+    revoked.insert(kpb.key.to_bytes());
+
+    let sig = ExtSignature::sign(&kpa, dhash.as_slice());
+    assert!(sig.verify_unrevoked(dhash.as_slice(), &revoked));
+
+    let sig = ExtSignature::sign(&kpb, dhash.as_slice());
+    assert!(sig.verify(dhash.as_slice()));
+    assert!(!sig.verify_unrevoked(dhash.as_slice(), &revoked));
+  }
+
+  #[test]
+  fn test_identity_key() {
+    let dhash = rand(64);
+    let p = SecretKey::rand();
+    let M = &p * G;
+
+    // the forged signature recovers its commitment, but the identity key is rejected
+    let sig = Signature { M, p };
+    assert!(sig.recover_commitment(&PublicKey::zero(), &dhash) == sig.M);
+    assert!(!sig.verify(&PublicKey::zero(), &dhash));
+
+    let forged = ExtSignature { sig, key: PublicKey::zero() };
+    assert!(!forged.verify(&dhash));
+    assert!(!ExtSignature::verify_batch(&[(forged, dhash)]));
+  }
+
+  #[test]
+  fn test_verify_with_challenge() {
+    let kpa = KeyPair::rand();
+    let dhash = Sha512::new()
+      .chain(rand(10))
+      .result();
+
+    // the challenge of a non-interactive signature opens to its own commitment
+    let sig = Signature::sign(&kpa, dhash.as_slice());
+    let c = Signature::challenge(&kpa.key, &sig.M, dhash.as_slice());
+    assert!(sig.verify_with_challenge(&kpa.key, &sig.M, &c) == sig.verify(&kpa.key, dhash.as_slice()));
+
+    // an externally supplied challenge, answered over the committed nonce
+    let (nonce, M) = Signature::commit();
+    let challenge = SecretKey::rand();
+    let sig = Signature::respond(nonce, &challenge, &kpa.secret);
+    assert!(sig.verify_with_challenge(&kpa.key, &M, &challenge));
+
+    assert!(!sig.verify_with_challenge(&kpa.key, &M, &SecretKey::rand()));
+    assert!(!sig.verify_with_challenge(&kpa.key, &Signature::commit().1, &challenge));
+    assert!(!sig.verify_with_challenge(&KeyPair::rand().key, &M, &challenge));
+  }
+
+  #[test]
+  fn test_commit_respond() {
+    let kpa = KeyPair::rand();
+    let dhash = Sha512::new()
+      .chain(rand(10))
+      .result();
+
+    let (m, M) = Signature::commit();
+    let hasher = Sha512::new()
+      .chain(kpa.key.to_bytes())
+      .chain(M.to_bytes())
+      .chain(dhash);
+
+    let c = SecretKey::from_hash(hasher);
+    let sig = Signature::respond(m, &c, &kpa.secret);
+    assert!(sig.verify(&kpa.key, dhash.as_slice()));
+    assert!(sig.M == M);
+
+    // every session draws a new nonce, unrelated to the key or the message
+    let (_, M2) = Signature::commit();
+    assert!(M2 != M && M2 != Signature::sign(&kpa, dhash.as_slice()).M);
+  }
+
+  #[test]
+  fn test_recover_commitment() {
+    let kpa = KeyPair::rand();
+    let dhash = Sha512::new()
+      .chain(rand(10))
+      .result();
+
+    let sig = Signature::sign(&kpa, dhash.as_slice());
+    let M = sig.recover_commitment(&kpa.key, dhash.as_slice());
+    assert!(M == sig.M);
+
+    // c = H(Y, M, dhash) opens the equation c*Y + p*G to the same point
+    let hasher = Sha512::new()
+      .chain(kpa.key.to_bytes())
+      .chain(M.to_bytes())
+      .chain(dhash);
+
+    assert!(SecretKey::from_hash(hasher) * kpa.key + &sig.p * G == M);
+    assert!(sig.recover_commitment(&kpa.key, &rand(10)) != M);
+  }
+
+  #[test]
+  fn test_verify_batch() {
+    let mut items = (0..8).map(|_| {
+      let d = rand(10);
+      (ExtSignature::sign(&KeyPair::rand(), &d), d)
+    }).collect::<Vec<_>>();
+
+    assert!(ExtSignature::verify_batch(&items));
+    assert!(ExtSignature::verify_batch(&[]));
+
+    // one bad signature among valid ones fails the whole batch
+    let kp = KeyPair::rand();
+    items[5].0 = ExtSignature::sign(&kp, &rand(10));
+    assert!(!ExtSignature::verify_batch(&items));
+
+    items[5].0 = ExtSignature::sign(&kp, &items[5].1);
+    assert!(ExtSignature::verify_batch(&items));
+  }
+
+  #[test]
+  fn test_musig() {
+    let kps = (0..3).map(|_| KeyPair::rand()).collect::<Vec<_>>();
+    let keys = kps.iter().map(|kp| kp.key).collect::<Vec<_>>();
+    let agg = aggregate_keys(&keys);
+    let dhash = rand(64);
+
+    // with tamper = Some(i), signer i sees a different commit set than the others
+    let sign = |messages: &[&[u8]], tamper: Option<usize>| {
+      let nonces = (0..3).map(|_| MuSigNonce::rand()).collect::<Vec<_>>();
+      let commits = nonces.iter().map(|n| n.commit).collect::<Vec<_>>();
+
+      let partials = kps.iter().zip(nonces).zip(messages.iter()).enumerate()
+        .map(|(i, ((kp, nonce), msg))| {
+          let mut view = commits.clone();
+          if tamper == Some(i) {
+            view[(i + 1) % 3].r2 = KeyPair::rand().key;
+          }
+
+          partial_sign(kp, &keys, nonce, &view, msg).unwrap()
+        })
+        .collect::<Vec<_>>();
+
+      aggregate_signatures(&partials)
+    };
+
+    let sig = sign(&[&dhash, &dhash, &dhash], None).unwrap();
+    assert!(sig.verify(&agg, &dhash));
+    assert!(!sig.verify(&kps[0].key, &dhash));
+
+    // fresh nonces give a different signature over the same message
+    let again = sign(&[&dhash, &dhash, &dhash], None).unwrap();
+    assert!(again.verify(&agg, &dhash) && again.M != sig.M);
+
+    // a signer on the wrong message, or with a different second nonce in its view, computes a different challenge
+    let other = rand(64);
+    assert!(sign(&[&dhash, &other, &dhash], None).is_err());
+    assert!(sign(&[&dhash, &dhash, &dhash], Some(0)).is_err());
+
+    let outsider = KeyPair::rand();
+    assert!(partial_sign(&outsider, &keys, MuSigNonce::rand(), &[], &dhash).is_err());
+  }
 }
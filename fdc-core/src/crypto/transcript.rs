@@ -0,0 +1,59 @@
+use sha2::{Digest, Sha512};
+
+use crate::crypto::SecretKey;
+
+//-----------------------------------------------------------------------------------------------------------
+// Transcript (labeled fields hashed into a challenge)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Clone)]
+pub struct Transcript {
+  hasher: Sha512
+}
+
+impl Transcript {
+  pub fn new(domain: &[u8]) -> Self {
+    let mut transcript = Self { hasher: Sha512::new() };
+    transcript.append(b"domain", domain);
+    transcript
+  }
+
+  // length prefixes keep (label, bytes) boundaries unambiguous
+  pub fn append(&mut self, label: &[u8], bytes: &[u8]) -> &mut Self {
+    self.hasher.input((label.len() as u64).to_le_bytes());
+    self.hasher.input(label);
+    self.hasher.input((bytes.len() as u64).to_le_bytes());
+    self.hasher.input(bytes);
+    self
+  }
+
+  pub fn challenge(&self) -> SecretKey {
+    SecretKey::from_hash(self.hasher.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::crypto::G;
+
+  #[test]
+  fn test_transcript() {
+    let mut t1 = Transcript::new(b"fdc-test");
+    t1.append(b"key", &G.to_bytes()).append(b"data", b"value");
+
+    let mut t2 = Transcript::new(b"fdc-test");
+    t2.append(b"key", &G.to_bytes()).append(b"data", b"value");
+    assert!(t1.challenge() == t2.challenge());
+
+    let mut t3 = Transcript::new(b"fdc-test");
+    t3.append(b"key", &G.to_bytes()).append(b"dat", b"avalue");
+    assert!(t1.challenge() != t3.challenge());
+
+    let mut t4 = Transcript::new(b"fdc-other");
+    t4.append(b"key", &G.to_bytes()).append(b"data", b"value");
+    assert!(t1.challenge() != t4.challenge());
+
+    t2.append(b"extra", b"");
+    assert!(t1.challenge() != t2.challenge());
+  }
+}
@@ -4,7 +4,7 @@ pub mod crypto;
 pub mod model;
 
 // -- generic definitions --
-pub type BoxError = Box<dyn std::error::Error>;
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T> = std::result::Result<T, BoxError>;
 
 #[inline]
@@ -1,8 +1,8 @@
-use sha2::{Digest, Sha512};
+use sha2::{Digest, Sha256, Sha512};
 use serde::{Serialize, Deserialize};
 use std::io::{Read, Write};
 
-use crate::{rand, Result};
+use crate::{error, rand, Result};
 use crate::crypto::*;
 
 pub fn salt(id: &str, table: &str) -> Vec<u8> {
@@ -14,14 +14,196 @@ pub fn salt(id: &str, table: &str) -> Vec<u8> {
   dhash.to_vec()
 }
 
+// creates independent head records using all available cores
+// the rayon feature runs on scoped std threads, the rayon crate isn't a dependency yet
+#[cfg(feature = "rayon")]
+pub fn create_heads_par(inputs: &[(KeyPair, PublicKey, Vec<u8>, RData)]) -> Result<Vec<(LambdaKey, Record)>> {
+  let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+  let chunk = inputs.len().div_ceil(threads).max(1);
+
+  std::thread::scope(|scope| {
+    let workers = inputs.chunks(chunk)
+      .map(|part| scope.spawn(move || {
+        part.iter()
+          .map(|(keyp, ekey, salt, rd)| Record::head(keyp, ekey, salt, rd.clone()))
+          .collect::<Vec<_>>()
+      }))
+      .collect::<Vec<_>>();
+
+    let mut heads = Vec::with_capacity(inputs.len());
+    for w in workers {
+      heads.extend(w.join().map_err(|_| error("Record creation thread panicked!"))?);
+    }
+
+    Ok(heads)
+  })
+}
+
+// combines t+1 partial decryptions of a record encrypted to a threshold group key
+pub fn combine_decryptions(parts: &[PublicShare], salt: &[u8]) -> Result<LambdaKey> {
+  if parts.is_empty() {
+    Err("No partial decryptions to combine!")?
+  }
+
+  let alpha = PublicShareVector(parts.to_vec()).recover()?;
+  Ok(LambdaKey::new(&alpha, salt))
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// Dn
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Clone, Eq, PartialEq)]
+pub struct Dn(Vec<u8>);
+
+// same encoding as the derived impl, but a length of no key size is rejected
+impl<'de> Deserialize<'de> for Dn {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename = "Dn")]
+    struct Encoded(Vec<u8>);
+
+    let Encoded(dn) = Encoded::deserialize(deserializer)?;
+    let sizes = [KeySize::S128, KeySize::S192, KeySize::S256, KeySize::S512];
+    if !sizes.iter().any(|ksize| ksize.size() == dn.len()) {
+      return Err(serde::de::Error::custom(format!("Dn length {} doesn't match any key size!", dn.len())))
+    }
+
+    Ok(Dn(dn))
+  }
+}
+
+impl Dn {
+  pub fn new(ksize: &KeySize, dn: Vec<u8>) -> Result<Self> {
+    if dn.len() != ksize.size() {
+      Err(format!("Dn length {} doesn't match the key size!", dn.len()))?
+    }
+
+    Ok(Self(dn))
+  }
+
+  fn rand(ksize: &KeySize) -> Self {
+    Self(rand(ksize.size()))
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl std::ops::Deref for Dn {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    &self.0
+  }
+}
+
 //-----------------------------------------------------------------------------------------------------------
 // RDataRef
 //-----------------------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[derive(Serialize, Clone, Eq, PartialEq)]
 pub struct RDataRef {
-  pub ksize: KeySize,
-  pub dn: Vec<u8>,
-  pub hfile: Vec<u8>
+  ksize: KeySize,
+  dn: Dn,
+  hfile: Vec<u8>
+}
+
+// same encoding as the derived impl, but dn must match ksize like in new
+impl<'de> Deserialize<'de> for RDataRef {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename = "RDataRef")]
+    struct Encoded {
+      ksize: KeySize,
+      dn: Dn,
+      hfile: Vec<u8>
+    }
+
+    let Encoded { ksize, dn, hfile } = Encoded::deserialize(deserializer)?;
+    if dn.len() != ksize.size() {
+      return Err(serde::de::Error::custom(format!("Dn length {} doesn't match the key size!", dn.len())))
+    }
+
+    Ok(Self { ksize, dn, hfile })
+  }
+}
+
+impl RDataRef {
+  pub fn new(ksize: KeySize, dn: Vec<u8>, hfile: &[u8]) -> Result<Self> {
+    let dn = Dn::new(&ksize, dn)?;
+    Ok(Self { ksize, dn, hfile: hfile.into() })
+  }
+
+  pub fn ksize(&self) -> &KeySize {
+    &self.ksize
+  }
+
+  pub fn dn(&self) -> &Dn {
+    &self.dn
+  }
+
+  pub fn hfile(&self) -> &[u8] {
+    &self.hfile
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// FileManifest (Merkle root over fixed-size chunk hashes, used as hfile)
+//-----------------------------------------------------------------------------------------------------------
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct FileManifest {
+  pub chunk_size: usize,
+  pub chunks: Vec<Vec<u8>>,
+  pub root: Vec<u8>
+}
+
+impl FileManifest {
+  // an empty file is a single empty chunk, so it still has a root
+  pub fn from_reader<R: Read>(chunk_size: usize, mut reader: R) -> Result<Self> {
+    if chunk_size == 0 {
+      Err("Chunk size must be positive!")?
+    }
+
+    let mut chunks = Vec::new();
+    let mut buffer = vec![0u8; chunk_size];
+    loop {
+      let n = FileManifest::fill(&mut reader, &mut buffer)?;
+      if n == 0 && !chunks.is_empty() {
+        break
+      }
+
+      chunks.push(Sha512::digest(&buffer[..n]).to_vec());
+      if n < chunk_size {
+        break
+      }
+    }
+
+    let root = merkle_root(&chunks).unwrap();
+    Ok(Self { chunk_size, chunks, root })
+  }
+
+  pub fn proof(&self, index: usize) -> Option<Vec<Vec<u8>>> {
+    merkle_proof(&self.chunks, index)
+  }
+
+  pub fn verify_chunk(&self, index: usize, data: &[u8], proof: &[Vec<u8>]) -> bool {
+    data.len() <= self.chunk_size && merkle_verify(&Sha512::digest(data), proof, index, self.chunks.len(), &self.root)
+  }
+
+  // reads until the buffer is full or the reader ends
+  fn fill<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buffer.len() {
+      match reader.read(&mut buffer[n..])? {
+        0 => break,
+        read => n += read
+      }
+    }
+
+    Ok(n)
+  }
 }
 
 //-----------------------------------------------------------------------------------------------------------
@@ -34,14 +216,24 @@ pub struct RData {
 }
 
 impl RData {
+  pub fn from_parts(lprev: Option<LambdaKey>, dref: RDataRef) -> Self {
+    Self { lprev, dref }
+  }
+
   pub fn head(ksize: KeySize, hfile: &[u8]) -> Self {
-    let dn = rand(ksize.size());
-    Self { lprev: None, dref: RDataRef { ksize: KeySize::S128, dn, hfile: hfile.into() } }
+    let dn = Dn::rand(&ksize);
+    Self { lprev: None, dref: RDataRef { ksize, dn, hfile: hfile.into() } }
+  }
+
+  // hfile is the manifest root, the manifest is kept by the caller to serve chunk proofs
+  pub fn head_file<R: Read>(ksize: KeySize, reader: R) -> Result<(Self, FileManifest)> {
+    let manifest = FileManifest::from_reader(FILE_CHUNK_SIZE, reader)?;
+    Ok((RData::head(ksize, &manifest.root), manifest))
   }
 
   pub fn tail(ksize: KeySize, lprev: LambdaKey, hfile: &[u8]) -> Self {
-    let dn = rand(ksize.size());
-    Self { lprev: Some(lprev), dref: RDataRef { ksize: KeySize::S128, dn, hfile: hfile.into() } }
+    let dn = Dn::rand(&ksize);
+    Self { lprev: Some(lprev), dref: RDataRef { ksize, dn, hfile: hfile.into() } }
   }
 }
 
@@ -50,38 +242,169 @@ impl RData {
 //-----------------------------------------------------------------------------------------------------------
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct REncData {
+  pub kn: PublicKey,
+  pub scheme: EncryptScheme, // also used for the split file reference
+  pub step: u32, // ratchet steps applied to the kn lambda, 0 for ECDH records
+  ciphertext: Vec<u8>,
+
+  pub split: Option<REncRef> // hfile encrypted to a separate recipient
+}
+
+// E_{lambda_ref} [hfile]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct REncRef {
   pub kn: PublicKey,
   ciphertext: Vec<u8>
 }
 
 impl REncData {
-  fn new(ekey: &PublicKey, salt: &[u8], rd: &RData) -> (LambdaKey, Self) {
+  // records are written with the AEAD scheme, a record tagged with a non-AEAD scheme decrypts without associated data
+  pub const SCHEME: EncryptScheme = EncryptScheme::XChaCha20Poly1305;
+
+  // canonical encoding used for signatures: kn (32 bytes) || len(ciphertext) (u64 LE) || ciphertext
+  // split records append the same encoding for the file reference, then scheme tag || step (u32 LE) closes it
+  pub fn to_signing_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(40 + self.ciphertext.len());
+    bytes.extend_from_slice(&self.kn.to_bytes());
+    bytes.extend_from_slice(&(self.ciphertext.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&self.ciphertext);
+
+    if let Some(split) = &self.split {
+      bytes.extend_from_slice(&split.kn.to_bytes());
+      bytes.extend_from_slice(&(split.ciphertext.len() as u64).to_le_bytes());
+      bytes.extend_from_slice(&split.ciphertext);
+    }
+
+    bytes.push(self.scheme.tag());
+    bytes.extend_from_slice(&self.step.to_le_bytes());
+    bytes
+  }
+
+  // the metadata (lprev, dn) and the hfile reference are encrypted to different keys, returns (meta, ref) lambdas
+  pub fn new_split(meta_ekey: &PublicKey, ref_ekey: &PublicKey, salt: &[u8], aad: &[u8], rd: &RData) -> (LambdaKey, LambdaKey, Self) {
+    let mut meta = rd.clone();
+    meta.dref.hfile = Vec::new();
+    let (meta_lambda, mut data) = REncData::new(meta_ekey, salt, aad, &meta);
+
+    let k = SecretKey::rand();
+    let ref_lambda = LambdaKey::new(&(&k * ref_ekey), salt);
+    let reference = REncData::encrypt(k * G, &ref_lambda, salt, aad, &rd.dref.hfile);
+
+    data.split = Some(REncRef { kn: reference.kn, ciphertext: reference.ciphertext });
+    (meta_lambda, ref_lambda, data)
+  }
+
+  // metadata only for split records, hfile is empty
+  pub fn data(&self, lambda: &LambdaKey, salt: &[u8], aad: &[u8]) -> Result<RData> {
+    // D_{lambda} [lprev, dn, hfile]
+    REncData::decrypt(self.scheme, &self.ciphertext, &self.kn, lambda, salt, aad)
+  }
+
+  pub fn file_ref(&self, ref_lambda: &LambdaKey, salt: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let split = self.split.as_ref().ok_or_else(|| error("Record has no split file reference!"))?;
+    REncData::decrypt(self.scheme, &split.ciphertext, &split.kn, ref_lambda, salt, aad)
+  }
+
+  pub fn data_split(&self, meta_lambda: &LambdaKey, ref_lambda: &LambdaKey, salt: &[u8], aad: &[u8]) -> Result<RData> {
+    let mut rd = self.data(meta_lambda, salt, aad)?;
+    rd.dref.hfile = self.file_ref(ref_lambda, salt, aad)?;
+    Ok(rd)
+  }
+
+  pub fn partial_decrypt(&self, share: &Share) -> PublicShare {
+    share * self.kn
+  }
+
+  fn new(ekey: &PublicKey, salt: &[u8], aad: &[u8], rd: &RData) -> (LambdaKey, Self) {
     let k = SecretKey::rand();
     let alpha = &k * ekey;
     let lambda = LambdaKey::new(&alpha, salt);
 
+    let data = REncData::encrypt(k * G, &lambda, salt, aad, rd);
+    (lambda, data)
+  }
+
+  // lambda = lprev.ratchet() under the previous kn, so the master derives it as L(master * kn).ratchet()^step
+  // and no earlier lambda is stored in the record
+  fn ratchet(prev: &REncData, lprev: &LambdaKey, salt: &[u8], aad: &[u8], rd: &RData) -> Result<(LambdaKey, Self)> {
+    if rd.lprev.is_some() {
+      Err("Ratcheted records don't store the previous lambda!")?
+    }
+
+    let step = prev.step.checked_add(1).ok_or_else(|| error("Ratchet step overflow!"))?;
+    let lambda = lprev.ratchet();
+
+    let mut data = REncData::encrypt(prev.kn, &lambda, salt, aad, rd);
+    data.step = step;
+    Ok((lambda, data))
+  }
+
+  // the ciphertext is bound to (salt, kn) and the caller context (Record::aad), so it doesn't decrypt
+  // under another subject/table, ephemeral key, chain position or owner
+  fn aad(salt: &[u8], kn: &PublicKey, context: &[u8]) -> Vec<u8> {
+    let mut aad = salt.to_vec();
+    aad.extend_from_slice(&kn.to_bytes());
+    aad.extend_from_slice(context);
+    aad
+  }
+
+  fn encrypt<T: Serialize>(kn: PublicKey, lambda: &LambdaKey, salt: &[u8], context: &[u8], rd: &T) -> Self {
     // E_{lambda} [lprev, dn, hfile]
     let from = bincode::serialize(rd).unwrap();
+    let aad = REncData::aad(salt, &kn, context);
     let mut to = Vec::new();
     {
       // encryption should not fail
-      let mut ecryptor = encryptor(EncryptScheme::AesCbc128, &lambda, &mut to).unwrap();
+      let mut ecryptor = encryptor(REncData::SCHEME, &REncData::SCHEME.ksize(), lambda, &aad, &mut to).unwrap();
       ecryptor.write_all(from.as_slice()).unwrap();
+      ecryptor.finish().unwrap();
     }
 
-    (lambda, Self { kn: (k * G), ciphertext: to })
+    Self { kn, scheme: REncData::SCHEME, step: 0, ciphertext: to, split: None }
   }
 
-  fn data(&self, lambda: &LambdaKey) -> Result<RData> {
-    // D_{lambda} [lprev, dn, hfile]
+  fn decrypt<T: serde::de::DeserializeOwned>(scheme: EncryptScheme, ciphertext: &[u8], kn: &PublicKey, lambda: &LambdaKey, salt: &[u8], context: &[u8]) -> Result<T> {
+    let aad = if scheme.is_aead() { REncData::aad(salt, kn, context) } else { Vec::new() };
     let mut to = Vec::new();
     {
-      let mut decryptor = decryptor(EncryptScheme::AesCbc128, lambda, self.ciphertext.as_slice())?;
+      let mut decryptor = decryptor(scheme, &scheme.ksize(), lambda, &aad, ciphertext)?;
       decryptor.read_to_end(&mut to)?;
     }
 
-    let cd: RData = bincode::deserialize(&to)?;
-    Ok(cd)
+    Ok(bincode::deserialize(&to)?)
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// HashAlgo (digest used for the record id and signature)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HashAlgo {
+  Sha512, Sha256
+}
+
+impl HashAlgo {
+  fn tag(&self) -> u8 {
+    match self {
+      HashAlgo::Sha512 => 0,
+      HashAlgo::Sha256 => 1
+    }
+  }
+}
+
+//-----------------------------------------------------------------------------------------------------------
+// RecordLink (preimage of a record id, proves the hash chain through a record without its signature)
+//-----------------------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct RecordLink {
+  pub hprev: Vec<u8>,
+  pub algo: HashAlgo,
+  pub data: Vec<u8> // REncData::to_signing_bytes()
+}
+
+impl RecordLink {
+  pub fn id(&self) -> Vec<u8> {
+    Record::hash_bytes(self.algo, &self.hprev, &self.data)
   }
 }
 
@@ -91,6 +414,7 @@ impl REncData {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Record {
   pub hprev: Vec<u8>,
+  pub algo: HashAlgo,
   data: REncData,
   sig: ExtSignature
 }
@@ -100,20 +424,108 @@ impl Record {
     &self.sig.key
   }
 
-  pub fn data(&self, lambda: &LambdaKey) -> Result<RData> {
-    self.data.data(lambda)
+  pub fn signature(&self) -> &ExtSignature {
+    &self.sig
+  }
+
+  pub fn id(&self) -> Vec<u8> {
+    Record::hash_with(self.algo, &self.hprev, &self.data)
+  }
+
+  pub fn link(&self) -> RecordLink {
+    RecordLink { hprev: self.hprev.clone(), algo: self.algo, data: self.data.to_signing_bytes() }
+  }
+
+  pub fn ephemeral_key(&self) -> &PublicKey {
+    &self.data.kn
+  }
+
+  // ratcheted records share the ephemeral key of the record they ratchet from
+  pub fn ratchet_step(&self) -> u32 {
+    self.data.step
+  }
+
+  // the identity as kn gives a fixed agreement point for any master key
+  pub fn check_ephemeral(&self) -> Result<()> {
+    if self.data.kn.is_identity() {
+      Err("Record ephemeral key is the identity point!")?
+    }
+
+    Ok(())
+  }
+
+  pub fn partial_decrypt(&self, share: &Share) -> PublicShare {
+    self.data.partial_decrypt(share)
+  }
+
+  pub fn ciphertext(&self) -> &[u8] {
+    &self.data.ciphertext
+  }
+
+  // associated data of the record payload: hprev || owner
+  pub fn aad(hprev: &[u8], owner: &PublicKey) -> Vec<u8> {
+    let mut aad = hprev.to_vec();
+    aad.extend_from_slice(&owner.to_bytes());
+    aad
+  }
+
+  pub fn data(&self, lambda: &LambdaKey, salt: &[u8]) -> Result<RData> {
+    self.data.data(lambda, salt, &Record::aad(&self.hprev, self.owner()))
+  }
+
+  pub fn file_ref(&self, ref_lambda: &LambdaKey, salt: &[u8]) -> Result<Vec<u8>> {
+    self.data.file_ref(ref_lambda, salt, &Record::aad(&self.hprev, self.owner()))
+  }
+
+  pub fn data_split(&self, meta_lambda: &LambdaKey, ref_lambda: &LambdaKey, salt: &[u8]) -> Result<RData> {
+    self.data.data_split(meta_lambda, ref_lambda, salt, &Record::aad(&self.hprev, self.owner()))
+  }
+
+  pub fn lambda(&self, master: &SecretKey, salt: &[u8]) -> LambdaKey {
+    let alpha = master * self.ephemeral_key();
+    (0..self.data.step).fold(LambdaKey::new(&alpha, salt), |lambda, _| lambda.ratchet())
   }
 
   pub fn head(keyp: &KeyPair, ekey: &PublicKey, salt: &[u8], rd: RData) -> (LambdaKey, Self) {
-    Record::create(keyp, ekey, salt, salt, rd)
+    Record::create(HashAlgo::Sha512, keyp, ekey, salt, salt, rd)
   }
 
   pub fn tail(keyp: &KeyPair, ekey: &PublicKey, hprev: &[u8], salt: &[u8], rd: RData) -> (LambdaKey, Self) {
-    Record::create(keyp, ekey, hprev, salt, rd)
+    Record::create(HashAlgo::Sha512, keyp, ekey, hprev, salt, rd)
+  }
+
+  // rd.dref.hfile is only readable with the ref lambda, returns (meta, ref) lambdas
+  pub fn tail_split(keyp: &KeyPair, meta_ekey: &PublicKey, ref_ekey: &PublicKey, hprev: &[u8], salt: &[u8], rd: RData) -> (LambdaKey, LambdaKey, Self) {
+    let (meta_lambda, ref_lambda, data) = REncData::new_split(meta_ekey, ref_ekey, salt, &Record::aad(hprev, &keyp.key), &rd);
+    (meta_lambda, ref_lambda, Record::sign(HashAlgo::Sha512, keyp, hprev, data))
+  }
+
+  // the record lambda is the lambda of prev ratcheted once, rd.lprev must be empty
+  // a leaked lambda exposes the records ratcheted after it but none before
+  pub fn tail_ratchet(keyp: &KeyPair, prev: &Record, lprev: &LambdaKey, salt: &[u8], rd: RData) -> Result<(LambdaKey, Self)> {
+    prev.data(lprev, salt).map_err(|_| error("Incorrect lambda for the previous record!"))?;
+
+    let hprev = prev.id();
+    let (lambda, data) = REncData::ratchet(&prev.data, lprev, salt, &Record::aad(&hprev, &keyp.key), &rd)?;
+    Ok((lambda, Record::sign(HashAlgo::Sha512, keyp, &hprev, data)))
+  }
+
+  // size is the number of records committed by root
+  pub fn verify_in_batch(&self, proof: &[Vec<u8>], index: usize, size: usize, root: &[u8]) -> bool {
+    self.check().is_ok() && merkle_verify(&self.id(), proof, index, size, root)
+  }
+
+  // head records are re-linked to the new salt, tails keep their hprev
+  pub fn reencrypt_salt(&self, master: &SecretKey, old_salt: &[u8], new_salt: &[u8], keyp: &KeyPair) -> Result<Record> {
+    let rd = self.data(&self.lambda(master, old_salt), old_salt)?;
+    let hprev = if self.hprev == old_salt { new_salt } else { &self.hprev };
+
+    let (_, record) = Record::create(self.algo, keyp, &(master * G), hprev, new_salt, rd);
+    Ok(record)
   }
 
   pub fn check(&self) -> Result<Vec<u8>> {
-    let dhash = Record::hash(&self.hprev, &self.data);
+    let dhash = self.id();
     if !self.sig.verify(&dhash) {
       Err("Invalid record signature!")?
     }
@@ -122,21 +534,43 @@ impl Record {
   }
 
   pub fn hash(hprev: &[u8], red: &REncData) -> Vec<u8> {
-    let b_data = bincode::serialize(red).unwrap();
     let dhash = Sha512::new()
       .chain(hprev)
-      .chain(b_data)
+      .chain(red.to_signing_bytes())
       .result();
 
     dhash.to_vec()
   }
 
-  fn create(keyp: &KeyPair, ekey: &PublicKey, hprev: &[u8], salt: &[u8], rd: RData) -> (LambdaKey, Self) {
-    let (lambda, data) = REncData::new(ekey, salt, &rd);
-    let dhash = Record::hash(hprev, &data);
-    
+  // SHA-512 is the digest of Record::hash, other algorithms are bound by a tag prefix
+  pub fn hash_with(algo: HashAlgo, hprev: &[u8], red: &REncData) -> Vec<u8> {
+    Record::hash_bytes(algo, hprev, &red.to_signing_bytes())
+  }
+
+  fn hash_bytes(algo: HashAlgo, hprev: &[u8], data: &[u8]) -> Vec<u8> {
+    match algo {
+      HashAlgo::Sha512 => Sha512::new()
+        .chain(hprev)
+        .chain(data)
+        .result().to_vec(),
+      HashAlgo::Sha256 => Sha256::new()
+        .chain([algo.tag()])
+        .chain(hprev)
+        .chain(data)
+        .result().to_vec()
+    }
+  }
+
+  fn create(algo: HashAlgo, keyp: &KeyPair, ekey: &PublicKey, hprev: &[u8], salt: &[u8], rd: RData) -> (LambdaKey, Self) {
+    let (lambda, data) = REncData::new(ekey, salt, &Record::aad(hprev, &keyp.key), &rd);
+    (lambda, Record::sign(algo, keyp, hprev, data))
+  }
+
+  fn sign(algo: HashAlgo, keyp: &KeyPair, hprev: &[u8], data: REncData) -> Self {
+    let dhash = Record::hash_with(algo, hprev, &data);
+
     let sig = ExtSignature::sign(keyp, dhash.as_slice());
-    (lambda, Self { hprev: hprev.to_vec(), data, sig })
+    Self { hprev: hprev.to_vec(), algo, data, sig }
   }
 }
 
@@ -155,9 +589,362 @@ mod tests {
       let (_, r1) = Record::head(&skp, &ekp.key, &salt, rd1.clone());
       assert!(r1.check().is_ok());
 
-      let alpha = ekp.secret * &r1.data.kn;
+      let alpha = ekp.secret * r1.data.kn;
       let lambda = LambdaKey::new(&alpha, &salt);
-      let rd2 = r1.data(&lambda).unwrap();
+      let rd2 = r1.data(&lambda, &salt).unwrap();
       assert!(rd1 == rd2);
     }
-  }
\ No newline at end of file
+
+    #[test]
+    fn rdata_keeps_ksize() {
+      let rd = RData::head(KeySize::S256, b"hfile");
+      assert!(rd.dref.ksize == KeySize::S256);
+      assert!(rd.dref.dn.len() == 256);
+
+      let rd = RData::tail(KeySize::S256, LambdaKey::new(&G, b"salt"), b"hfile");
+      assert!(rd.dref.ksize == KeySize::S256);
+      assert!(rd.dref.dn.len() == 256);
+    }
+
+    #[test]
+    fn file_manifest_chunks() {
+      let file = rand(3 * FILE_CHUNK_SIZE + 10);
+      let (rd, manifest) = RData::head_file(KeySize::S128, file.as_slice()).unwrap();
+      assert!(manifest.chunks.len() == 4);
+      assert!(rd.dref.hfile == manifest.root);
+
+      for (i, chunk) in file.chunks(FILE_CHUNK_SIZE).enumerate() {
+        let proof = manifest.proof(i).unwrap();
+        assert!(manifest.verify_chunk(i, chunk, &proof));
+        assert!(!manifest.verify_chunk((i + 1) % 4, chunk, &proof));
+      }
+
+      let mut chunk = file[FILE_CHUNK_SIZE..2 * FILE_CHUNK_SIZE].to_vec();
+      chunk[0] ^= 1;
+      assert!(!manifest.verify_chunk(1, &chunk, &manifest.proof(1).unwrap()));
+
+      let exact = FileManifest::from_reader(16, &file[..32]).unwrap();
+      assert!(exact.chunks.len() == 2);
+
+      let empty = FileManifest::from_reader(16, &[][..]).unwrap();
+      assert!(empty.chunks.len() == 1);
+
+      // appending a copy of the last chunk changes the root
+      let padded = [&file[..48], &file[32..48]].concat();
+      let m48 = FileManifest::from_reader(16, &file[..48]).unwrap();
+      let m64 = FileManifest::from_reader(16, padded.as_slice()).unwrap();
+      assert!(m48.chunks.len() == 3 && m64.chunks.len() == 4);
+      assert!(m48.root != m64.root);
+      assert!(!m48.verify_chunk(3, &file[32..48], &m48.proof(2).unwrap()));
+    }
+
+    #[test]
+    fn rdata_from_parts() {
+      let salt = salt("subject-id", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let dref = RDataRef::new(KeySize::S128, vec![7u8; 128], b"data-url").unwrap();
+      let rd1 = RData::from_parts(Some(LambdaKey::new(&G, &salt)), dref);
+      let (lambda, r1) = Record::tail(&skp, &ekp.key, &rand(64), &salt, rd1.clone());
+
+      assert!(r1.data(&lambda, &salt).unwrap() == rd1);
+    }
+
+    #[test]
+    fn rdata_ref_dn_length() {
+      assert!(RDataRef::new(KeySize::S256, vec![7u8; 256], b"data-url").is_ok());
+      assert!(RDataRef::new(KeySize::S256, vec![7u8; 128], b"data-url").is_err());
+      assert!(RDataRef::new(KeySize::S128, vec![], b"data-url").is_err());
+
+      // decoding enforces the same invariant, here a 128 bytes dn under S256
+      let dref = RDataRef::new(KeySize::S128, vec![7u8; 128], b"data-url").unwrap();
+      let mut encoded = bincode::serialize(&dref).unwrap();
+      assert!(bincode::deserialize::<RDataRef>(&encoded).unwrap() == dref);
+      encoded[..4].copy_from_slice(&bincode::serialize(&KeySize::S256).unwrap());
+      assert!(bincode::deserialize::<RDataRef>(&encoded).is_err());
+
+      assert!(bincode::deserialize::<Dn>(&bincode::serialize(&vec![7u8; 128]).unwrap()).is_ok());
+      assert!(bincode::deserialize::<Dn>(&bincode::serialize(&vec![7u8; 100]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn signing_bytes_stable() {
+      let mut red = REncData { kn: G, scheme: EncryptScheme::AesCbc128, step: 0, ciphertext: vec![1, 2, 3], split: None };
+
+      let mut expected = G.to_bytes().to_vec();
+      expected.extend_from_slice(&[3, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+      expected.extend_from_slice(&[0, 0, 0, 0, 0]);
+      assert!(red.to_signing_bytes() == expected);
+
+      red.scheme = EncryptScheme::XChaCha20Poly1305;
+      red.step = 2;
+      expected.truncate(expected.len() - 5);
+      expected.extend_from_slice(&[3, 2, 0, 0, 0]);
+      assert!(red.to_signing_bytes() == expected);
+    }
+
+    #[test]
+    fn record_non_aead_scheme() {
+      let salt = salt("subject-id", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      // an AES-CBC record is encrypted over the bare RData, without associated data
+      let rd = RData::head(KeySize::S128, b"data-url");
+      let k = SecretKey::rand();
+      let lambda = LambdaKey::new(&(&k * ekp.key), &salt);
+
+      let mut ciphertext = Vec::new();
+      let mut enc = encryptor(EncryptScheme::AesCbc128, &KeySize::S128, &lambda, &[], &mut ciphertext).unwrap();
+      enc.write_all(&bincode::serialize(&rd).unwrap()).unwrap();
+      enc.finish().unwrap();
+
+      let red = REncData { kn: k * G, scheme: EncryptScheme::AesCbc128, step: 0, ciphertext, split: None };
+      let record = Record::sign(HashAlgo::Sha512, &skp, &salt, red);
+      assert!(record.check().is_ok());
+      assert!(record.data(&record.lambda(&ekp.secret, &salt), &salt).unwrap() == rd);
+
+      // the scheme is signed, so it can't be switched on a stored record
+      let mut tampered = record.clone();
+      tampered.data.scheme = EncryptScheme::XChaCha20Poly1305;
+      assert!(tampered.check().is_err());
+    }
+
+    #[test]
+    fn record_ephemeral_key() {
+      let salt = salt("subject-id", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let rd1 = RData::head(KeySize::S128, b"data-url");
+      let (l1, r1) = Record::head(&skp, &ekp.key, &salt, rd1.clone());
+
+      let alpha = &ekp.secret * r1.ephemeral_key();
+      let lambda = LambdaKey::new(&alpha, &salt);
+      assert!(lambda == l1);
+      assert!(r1.data(&lambda, &salt).unwrap() == rd1);
+    }
+
+    #[test]
+    fn record_threshold_decrypt() {
+      let salt = salt("subject-id", "table-id");
+
+      let threshold = 2;
+      let secret = SecretKey::rand();
+      let ekey = &secret * G;
+      let shares = Polynomial::rand(secret, threshold).shares(5);
+
+      let skp = KeyPair::rand();
+      let rd1 = RData::head(KeySize::S128, b"data-url");
+      let (_, r1) = Record::head(&skp, &ekey, &salt, rd1.clone());
+
+      let parts = shares.0.iter()
+        .skip(1)
+        .take(threshold + 1)
+        .map(|s| r1.partial_decrypt(s))
+        .collect::<Vec<_>>();
+
+      let lambda = combine_decryptions(&parts, &salt).unwrap();
+      assert!(r1.data(&lambda, &salt).unwrap() == rd1);
+
+      let lambda = combine_decryptions(&parts[..threshold], &salt).unwrap();
+      assert!(r1.data(&lambda, &salt).is_err());
+    }
+
+    #[test]
+    fn record_bound_to_salt() {
+      let other = salt("other-id", "table-id");
+      let salt = salt("subject-id", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let rd1 = RData::head(KeySize::S128, b"data-url");
+      let (lambda, r1) = Record::head(&skp, &ekp.key, &salt, rd1.clone());
+      assert!(r1.data(&lambda, &salt).unwrap() == rd1);
+
+      // the right lambda under a different salt is rejected
+      assert!(r1.data(&lambda, &other).is_err());
+
+      // as is a swapped ephemeral key
+      let mut r2 = r1.clone();
+      r2.data.kn = KeyPair::rand().key;
+      assert!(r2.data(&lambda, &salt).is_err());
+    }
+
+    #[test]
+    fn record_bound_to_position_and_owner() {
+      let salt = salt("subject-id", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let (l1, r1) = Record::head(&skp, &ekp.key, &salt, RData::head(KeySize::S128, b"data-url"));
+      let rd2 = RData::tail(KeySize::S128, l1, b"data-url-2");
+      let (l2, r2) = Record::tail(&skp, &ekp.key, &r1.id(), &salt, rd2.clone());
+      assert!(r2.data(&l2, &salt).unwrap() == rd2);
+
+      // the payload re-signed at another chain position doesn't decrypt
+      let moved = Record::sign(HashAlgo::Sha512, &skp, &salt, r2.data.clone());
+      assert!(moved.check().is_ok());
+      assert!(moved.data(&l2, &salt).is_err());
+
+      // and neither does it under another owner
+      let stolen = Record::sign(HashAlgo::Sha512, &KeyPair::rand(), &r2.hprev, r2.data.clone());
+      assert!(stolen.check().is_ok());
+      assert!(stolen.data(&l2, &salt).is_err());
+    }
+
+    #[test]
+    fn record_truncated_ciphertext() {
+      let salt = salt("subject-id", "table-id");
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let (lambda, mut r1) = Record::head(&skp, &ekp.key, &salt, RData::head(KeySize::S128, b"data-url"));
+      let len = r1.data.ciphertext.len();
+      r1.data.ciphertext.truncate(len - 3);
+
+      let err = r1.data(&lambda, &salt).err().unwrap();
+      assert!(err.to_string().starts_with("Decryption error: "));
+    }
+
+    #[test]
+    fn record_hash_algo() {
+      let salt = salt("subject-id", "table-id");
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let (_, r1) = Record::head(&skp, &ekp.key, &salt, RData::head(KeySize::S128, b"data-url"));
+      let (_, r2) = Record::create(HashAlgo::Sha256, &skp, &ekp.key, &salt, &salt, RData::head(KeySize::S128, b"data-url"));
+
+      assert!(r1.algo == HashAlgo::Sha512 && r1.check().unwrap().len() == 64);
+      assert!(r1.id() == Record::hash(&r1.hprev, &r1.data));
+      assert!(r2.algo == HashAlgo::Sha256 && r2.check().unwrap().len() == 32);
+
+      // the tag is covered by the signature
+      let mut f1 = r1.clone();
+      f1.algo = HashAlgo::Sha256;
+      assert!(f1.check().is_err());
+
+      let mut f2 = r2.clone();
+      f2.algo = HashAlgo::Sha512;
+      assert!(f2.check().is_err());
+    }
+
+    #[test]
+    fn record_check_ephemeral() {
+      let salt = salt("subject-id", "table-id");
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let (_, mut r1) = Record::head(&skp, &ekp.key, &salt, RData::head(KeySize::S128, b"data-url"));
+      assert!(r1.check_ephemeral().is_ok());
+
+      r1.data.kn = PublicKey::zero();
+      assert!(r1.check_ephemeral().is_err());
+    }
+
+    #[test]
+    fn record_split_encryption() {
+      let salt = salt("subject-id", "table-id");
+      let meta_kp = KeyPair::rand();
+      let ref_kp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let rd = RData::tail(KeySize::S128, LambdaKey::new(&G, &salt), b"hfile-reference");
+      let (meta_lambda, ref_lambda, r1) = Record::tail_split(&skp, &meta_kp.key, &ref_kp.key, &rand(64), &salt, rd.clone());
+      assert!(r1.check().is_ok());
+
+      // the ref key holder only reads the file reference
+      let derived = LambdaKey::new(&(&ref_kp.secret * r1.data.split.as_ref().unwrap().kn), &salt);
+      assert!(derived == ref_lambda);
+      assert!(r1.file_ref(&ref_lambda, &salt).unwrap() == b"hfile-reference");
+      assert!(r1.data(&ref_lambda, &salt).ok() != Some(rd.clone()));
+
+      let meta = r1.data(&meta_lambda, &salt).unwrap();
+      assert!(meta.lprev == rd.lprev && meta.dref.dn == rd.dref.dn && meta.dref.hfile.is_empty());
+      assert!(r1.data_split(&meta_lambda, &ref_lambda, &salt).unwrap() == rd);
+
+      // the split part is covered by the signature
+      let mut r2 = r1.clone();
+      r2.data.split.as_mut().unwrap().ciphertext[0] ^= 1;
+      assert!(r2.check().is_err());
+
+      let (_, r3) = Record::head(&skp, &meta_kp.key, &salt, RData::head(KeySize::S128, b"hfile"));
+      assert!(r3.file_ref(&ref_lambda, &salt).is_err());
+    }
+
+    #[test]
+    fn record_in_batch() {
+      let salt = salt("subject-id", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let batch = (0..3).map(|i| {
+        let rd = RData::head(KeySize::S128, format!("data-url-{}", i).as_bytes());
+        Record::head(&skp, &ekp.key, &salt, rd).1
+      }).collect::<Vec<_>>();
+
+      let leaves = batch.iter().map(|r| r.id()).collect::<Vec<_>>();
+      let root = merkle_root(&leaves).unwrap();
+
+      let mut proof = merkle_proof(&leaves, 1).unwrap();
+      assert!(batch[1].verify_in_batch(&proof, 1, 3, &root));
+      assert!(!batch[0].verify_in_batch(&proof, 1, 3, &root));
+
+      // the last record doesn't verify past the end of the batch
+      let last = merkle_proof(&leaves, 2).unwrap();
+      assert!(batch[2].verify_in_batch(&last, 2, 3, &root));
+      assert!(!batch[2].verify_in_batch(&last, 3, 3, &root));
+
+      proof[0][0] ^= 1;
+      assert!(!batch[1].verify_in_batch(&proof, 1, 3, &root));
+    }
+
+    #[test]
+    fn record_reencrypt_salt() {
+      let old_salt = salt("subject-id", "table-id");
+      let new_salt = salt("subject-id-v2", "table-id");
+
+      let ekp = KeyPair::rand();
+      let skp = KeyPair::rand();
+
+      let rd1 = RData::head(KeySize::S128, b"data-url");
+      let (_, r1) = Record::head(&skp, &ekp.key, &old_salt, rd1.clone());
+
+      let r2 = r1.reencrypt_salt(&ekp.secret, &old_salt, &new_salt, &skp).unwrap();
+      assert!(r2.check().is_ok());
+      assert!(r2.hprev == new_salt);
+      assert!(r2.data(&r2.lambda(&ekp.secret, &new_salt), &new_salt).unwrap() == rd1);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn create_heads_parallel() {
+      let skp = KeyPair::rand();
+      let ekp = KeyPair::rand();
+
+      let inputs = (0..16).map(|i| {
+        let salt = salt(&format!("subject-{}", i), "table-id");
+        let rd = RData::head(KeySize::S128, format!("data-url-{}", i).as_bytes());
+        (skp.clone(), ekp.key, salt, rd)
+      }).collect::<Vec<_>>();
+
+      let par = create_heads_par(&inputs).unwrap();
+      assert!(par.len() == inputs.len());
+
+      for ((keyp, ekey, salt, rd), (lambda, record)) in inputs.iter().zip(par.iter()) {
+        let (_, serial) = Record::head(keyp, ekey, salt, rd.clone());
+        assert!(record.check().is_ok());
+        assert!(record.hprev == serial.hprev);
+        assert!(record.owner() == serial.owner());
+        assert!(record.data(lambda, salt).unwrap() == serial.data(&serial.lambda(&ekp.secret, salt), salt).unwrap());
+      }
+    }
+  }